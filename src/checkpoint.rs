@@ -0,0 +1,180 @@
+use std::{
+    fs::{self, File}, 
+    io::{self, BufReader, BufWriter, Read, Write}, 
+    path::Path
+};
+use crate::{
+    vec3::Vec3, 
+    raytracing::{
+        Film, 
+        SamplerKind, 
+        adaptive::PixelStats
+    }
+};
+
+
+const MAGIC: &[u8; 8] = b"RTCHECK3";
+
+/*
+    What is needed to go on with a render that was stopped, saved along 
+    with the film of the samples added up so far: the seed and sampler 
+    they were taken with and a fingerprint of the scene they were taken of
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Checkpoint {
+    pub seed: u64, 
+    pub sampler: SamplerKind, 
+    pub scene_fingerprint: u64
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("bad checkpoint: {}", message))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(input)?))
+}
+
+impl Checkpoint {
+    /*
+        The header followed by the sum, sample count, mean and spread of 
+        every pixel, the sums of its layers and its nearest hit, all little endian
+    */
+    pub fn write(&self, film: &Film, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        for value in [film.width() as u64, film.height() as u64, self.seed, self.scene_fingerprint].iter() {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        let sampler = self.sampler.name().as_bytes();
+        out.write_all(&[sampler.len() as u8])?;
+        out.write_all(sampler)?;
+
+        for pixel in film.pixels() {
+            let (count, mean, m2) = pixel.stats.parts();
+            for value in [pixel.sum.x, pixel.sum.y, pixel.sum.z, mean, m2].iter() {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            out.write_all(&count.to_le_bytes())?;
+            for value in [pixel.normal.x, pixel.normal.y, pixel.normal.z, pixel.albedo.x, pixel.albedo.y, pixel.albedo.z, pixel.depth].iter() {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        Reads a checkpoint of a 'width' x 'height' render, a checkpoint of 
+        another size is an error found before the film is allocated
+    */
+    pub fn read(input: &mut impl Read, width: usize, height: usize) -> io::Result<(Checkpoint, Film)> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a checkpoint file"));
+        }
+
+        let size = (read_u64(input)?, read_u64(input)?);
+        if size != (width as u64, height as u64) {
+            let message = format!("it is {}x{}, not {}x{}", size.0, size.1, width, height);
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let seed = read_u64(input)?;
+        let scene_fingerprint = read_u64(input)?;
+
+        let mut length = [0; 1];
+        input.read_exact(&mut length)?;
+        let mut sampler = vec![0; length[0] as usize];
+        input.read_exact(&mut sampler)?;
+        let sampler = String::from_utf8_lossy(&sampler).parse().map_err(|e: String| invalid_data(&e))?;
+
+        let mut film = Film::new(width, height);
+        for pixel in film.pixels_mut() {
+            pixel.sum = Vec3::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            let (mean, m2) = (read_f64(input)?, read_f64(input)?);
+            let mut count = [0; 4];
+            input.read_exact(&mut count)?;
+            pixel.stats = PixelStats::from_parts(u32::from_le_bytes(count), mean, m2);
+            pixel.normal = Vec3::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            pixel.albedo = Vec3::new(read_f64(input)?, read_f64(input)?, read_f64(input)?);
+            pixel.depth = read_f64(input)?;
+        }
+
+        Ok((Checkpoint {seed, sampler, scene_fingerprint}, film))
+    }
+
+    /*
+        Writes next to 'path' first and then moves the file over it, so that 
+        being stopped while saving leaves the previous checkpoint whole. 
+        When saving fails the partial file is removed
+    */
+    pub fn save(&self, film: &Film, path: &Path) -> io::Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".part");
+        let mut out = BufWriter::new(File::create(&partial)?);
+        let result = self.write(film, &mut out).and_then(|_| out.flush());
+        drop(out);
+        let result = result.and_then(|_| fs::rename(&partial, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        result
+    }
+
+    pub fn load(path: &Path, width: usize, height: usize) -> io::Result<(Checkpoint, Film)> {
+        Checkpoint::read(&mut BufReader::new(File::open(path)?), width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::PathSample;
+
+    #[test]
+    fn round_trips_exactly() {
+        let mut film = Film::new(3, 2);
+        for (i, pixel) in film.pixels_mut().iter_mut().enumerate() {
+            for j in 0..i {
+                pixel.add(&PathSample {
+                    radiance: Vec3::new(i as f64 * 0.1, j as f64 / 3., 1e-9), 
+                    normal: Vec3::new(0., 1., 0.), 
+                    albedo: Vec3::from_float(j as f64 * 0.2), 
+                    depth: if j == 1 { f64::INFINITY } else { i as f64 }
+                });
+            }
+        }
+        let checkpoint = Checkpoint {seed: 42, sampler: SamplerKind::Halton, scene_fingerprint: 0xdead_beef};
+
+        let mut data = vec![];
+        checkpoint.write(&film, &mut data).unwrap();
+        let (reloaded, reloaded_film) = Checkpoint::read(&mut data.as_slice(), 3, 2).unwrap();
+
+        assert_eq!(reloaded, checkpoint);
+        assert_eq!((reloaded_film.width(), reloaded_film.height()), (3, 2));
+        for (a, b) in reloaded_film.pixels().iter().zip(film.pixels()) {
+            assert_eq!(a.sum, b.sum);
+            assert_eq!(a.stats.parts(), b.stats.parts());
+            assert_eq!((a.normal, a.albedo, a.depth), (b.normal, b.albedo, b.depth));
+        }
+
+        assert!(Checkpoint::read(&mut &data[..data.len() - 1], 3, 2).is_err());
+        assert!(Checkpoint::read(&mut &b"P6\n1 1\n255\n"[..], 3, 2).is_err());
+        assert!(Checkpoint::read(&mut data.as_slice(), 2, 3).is_err());
+    }
+
+    #[test]
+    fn huge_sizes_are_not_allocated() {
+        let mut data = MAGIC.to_vec();
+        for value in [u64::MAX, u64::MAX, 0, 0].iter() {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let error = Checkpoint::read(&mut data.as_slice(), 640, 480).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,407 @@
+use std::{
+    fmt, 
+    io::{self, Write}, 
+    str::FromStr
+};
+use flate2::{Compression as Level, write::ZlibEncoder};
+use crate::image_formats::FloatImage;
+
+
+// The first 4 bytes of every OpenEXR file
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+// Version 2, single part, scanlines
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+// ZIP compresses blocks of this many scanlines together
+const ZIP_LINES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelType {
+    Half, 
+    Float
+}
+
+impl PixelType {
+    pub const ALL: [PixelType; 2] = [PixelType::Half, PixelType::Float];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PixelType::Half => "half", 
+            PixelType::Float => "float"
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PixelType::Half => 2, 
+            PixelType::Float => 4
+        }
+    }
+}
+
+impl fmt::Display for PixelType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for PixelType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PixelType, String> {
+        PixelType::ALL.iter().copied().find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("unknown pixel type '{}', expected half or float", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None, 
+    Zip
+}
+
+impl Compression {
+    pub const ALL: [Compression; 2] = [Compression::None, Compression::Zip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none", 
+            Compression::Zip => "zip"
+        }
+    }
+
+    fn lines_per_block(&self) -> usize {
+        match self {
+            Compression::None => 1, 
+            Compression::Zip => ZIP_LINES
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Compression, String> {
+        Compression::ALL.iter().copied().find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("unknown compression '{}', expected none or zip", s))
+    }
+}
+
+struct Channel {
+    name: String, 
+    values: Vec<f64>
+}
+
+/*
+    An OpenEXR image made of named channels of linear values, like "R" or
+    "normal.X". Channels named "layer.something" make up the extra layer
+    "layer", the ones without a dot the main picture
+*/
+pub struct ExrImage {
+    width: usize, 
+    height: usize, 
+    pixel_type: PixelType, 
+    compression: Compression, 
+    channels: Vec<Channel>
+}
+
+impl ExrImage {
+    pub fn new(width: usize, height: usize, pixel_type: PixelType, compression: Compression) -> ExrImage {
+        ExrImage {width, height, pixel_type, compression, channels: vec![]}
+    }
+
+    /*
+        Adds the channel 'name' with a value for every pixel, row by row
+        from the top
+    */
+    pub fn add_channel(&mut self, name: &str, values: Vec<f64>) {
+        assert_eq!(values.len(), self.width * self.height, "channel {} has the wrong size", name);
+        self.channels.push(Channel {name: name.to_string(), values});
+    }
+
+    /*
+        Adds the colors of 'image' as the channels R, G and B of 'layer', 
+        or of the main picture when 'layer' is empty
+    */
+    pub fn add_color(&mut self, layer: &str, image: &FloatImage) {
+        self.add_vectors(layer, ["R", "G", "B"], image);
+    }
+
+    // Adds the vectors in 'image', like normals, as the channels X, Y and Z of 'layer'
+    pub fn add_directions(&mut self, layer: &str, image: &FloatImage) {
+        self.add_vectors(layer, ["X", "Y", "Z"], image);
+    }
+
+    fn add_vectors(&mut self, layer: &str, names: [&str; 3], image: &FloatImage) {
+        let prefix = if layer.is_empty() { String::new() } else { format!("{}.", layer) };
+        let pixels = image.pixels();
+        self.add_channel(&format!("{}{}", prefix, names[0]), pixels.iter().map(|p| p.x).collect());
+        self.add_channel(&format!("{}{}", prefix, names[1]), pixels.iter().map(|p| p.y).collect());
+        self.add_channel(&format!("{}{}", prefix, names[2]), pixels.iter().map(|p| p.z).collect());
+    }
+
+    pub fn write_image_data(&self, out: &mut impl Write) -> io::Result<()> {
+        // Readers expect the channels sorted by name, in the header and in the pixels
+        let mut channels: Vec<&Channel> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+
+        let mut header = vec![];
+        header.extend_from_slice(&MAGIC);
+        let mut version = VERSION;
+        // Long names flag, for names over 31 bytes
+        if channels.iter().any(|channel| channel.name.len() > 31) {
+            version[1] |= 0x04;
+        }
+        header.extend_from_slice(&version);
+
+        let mut channel_list = vec![];
+        for channel in channels.iter() {
+            channel_list.extend_from_slice(channel.name.as_bytes());
+            channel_list.push(0);
+            let pixel_type: i32 = match self.pixel_type {
+                PixelType::Half => 1, 
+                PixelType::Float => 2
+            };
+            channel_list.extend_from_slice(&pixel_type.to_le_bytes());
+            // Not perceptually linear, 3 reserved bytes, one sample per pixel on both axes
+            channel_list.extend_from_slice(&[0, 0, 0, 0]);
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+            channel_list.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channel_list.push(0);
+        attribute(&mut header, "channels", "chlist", &channel_list);
+
+        let compression = match self.compression {
+            Compression::None => 0, 
+            Compression::Zip => 3
+        };
+        attribute(&mut header, "compression", "compression", &[compression]);
+        let window: Vec<u8> = [0, 0, self.width as i32 - 1, self.height as i32 - 1].iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+        // From the top row down
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+
+        let lines_per_block = self.compression.lines_per_block();
+        let blocks: Vec<Vec<u8>> = (0..self.height).step_by(lines_per_block).map(|first_line| {
+            let lines = first_line..(first_line + lines_per_block).min(self.height);
+            let mut data = Vec::with_capacity(lines.len() * self.width * channels.len() * self.pixel_type.size());
+            for y in lines {
+                for channel in channels.iter() {
+                    for value in &channel.values[y * self.width..(y + 1) * self.width] {
+                        match self.pixel_type {
+                            PixelType::Half => data.extend_from_slice(&to_half(*value as f32).to_le_bytes()), 
+                            PixelType::Float => data.extend_from_slice(&(*value as f32).to_le_bytes())
+                        }
+                    }
+                }
+            }
+
+            let data = match self.compression {
+                Compression::None => data, 
+                Compression::Zip => {
+                    let compressed = zip_compress(&data)?;
+                    // Blocks that don't get smaller are stored as they are
+                    if compressed.len() < data.len() { compressed } else { data }
+                }
+            };
+            let mut block = Vec::with_capacity(data.len() + 8);
+            block.extend_from_slice(&(first_line as i32).to_le_bytes());
+            block.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            block.extend_from_slice(&data);
+            Ok(block)
+        }).collect::<io::Result<_>>()?;
+
+        // Where every block starts from the beginning of the file
+        let mut offset = (header.len() + 8 * blocks.len()) as u64;
+        out.write_all(&header)?;
+        for block in blocks.iter() {
+            out.write_all(&offset.to_le_bytes())?;
+            offset += block.len() as u64;
+        }
+        for block in blocks.iter() {
+            out.write_all(block)?;
+        }
+        Ok(())
+    }
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/*
+    ZIP compression the way OpenEXR does it: the first bytes of the
+    values are put before the second ones, and each byte is replaced by
+    its difference with the previous one. Close values then give many
+    equal bytes, which deflate compresses well
+*/
+fn zip_compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reordered: Vec<u8> = data.iter().step_by(2).chain(data.iter().skip(1).step_by(2)).copied().collect();
+    let mut previous = reordered.first().copied().unwrap_or(0);
+    for byte in reordered.iter_mut().skip(1) {
+        let value = *byte;
+        *byte = (value as i32 - previous as i32 + 128 + 256) as u8;
+        previous = value;
+    }
+
+    let mut encoder = ZlibEncoder::new(vec![], Level::default());
+    encoder.write_all(&reordered)?;
+    encoder.finish()
+}
+
+/*
+    The 16 bit float closest to 'value', rounding to even on ties. Values
+    too big for it become infinite, too small ones lose precision down to
+    zero
+*/
+pub fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity, NaN stays NaN
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Subnormal: the leading 1 becomes part of the mantissa
+        let shift = (14 - exponent) as u32;
+        if shift > 24 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let half = mantissa >> shift;
+        let rest = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = rest > halfway || (rest == halfway && half & 1 == 1);
+        return sign | (half + round_up as u32) as u16;
+    }
+
+    // Rounding can carry into the exponent, up to infinity
+    let bits = ((exponent as u32) << 23) | mantissa;
+    let bits = bits + 0xfff + ((bits >> 13) & 1);
+    sign | (bits >> 13) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+    use flate2::read::ZlibDecoder;
+    use std::{convert::TryInto, io::Read};
+
+    #[test]
+    fn halves() {
+        let cases = [
+            (1., 0x3c00), (-2., 0xc000), (0.5, 0x3800), (0.1, 0x2e66), 
+            (65504., 0x7bff), (65520., 0x7c00), (1e10, 0x7c00), 
+            (5.960_464_5e-8, 0x0001), (6.103_515_6e-5, 0x0400), (1e-9, 0), 
+            (f32::INFINITY, 0x7c00), (f32::NEG_INFINITY, 0xfc00)
+        ];
+        for (value, half) in cases.iter() {
+            assert_eq!(to_half(*value), *half, "{}", value);
+        }
+        assert_eq!(to_half(f32::NAN) & 0x7c00, 0x7c00);
+        assert_ne!(to_half(f32::NAN) & 0x3ff, 0);
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+    }
+
+    /*
+        Reads the values of the blocks of a file written by us back, 
+        undoing the ZIP compression
+    */
+    fn blocks(data: &[u8], blocks: usize, block_size: usize) -> Vec<Vec<u8>> {
+        // Skips the attributes of the header, made of name, type, size and 
+        // value, up to the empty name that ends it. The offsets come next
+        let mut offsets_start = 8;
+        while data[offsets_start] != 0 {
+            let name_end = offsets_start + data[offsets_start..].iter().position(|b| *b == 0).unwrap();
+            let type_end = name_end + 1 + data[name_end + 1..].iter().position(|b| *b == 0).unwrap();
+            let size = u32_at(data, type_end + 1) as usize;
+            offsets_start = type_end + 5 + size;
+        }
+        offsets_start += 1;
+
+        (0..blocks).map(|i| {
+            let offset = u64::from_le_bytes(data[offsets_start + 8 * i..offsets_start + 8 * i + 8].try_into().unwrap()) as usize;
+            let size = u32_at(data, offset + 4) as usize;
+            let packed = &data[offset + 8..offset + 8 + size];
+            if size == block_size {
+                return packed.to_vec();
+            }
+
+            let mut reordered = vec![];
+            ZlibDecoder::new(packed).read_to_end(&mut reordered).unwrap();
+            for i in 1..reordered.len() {
+                reordered[i] = (reordered[i - 1] as i32 + reordered[i] as i32 - 128) as u8;
+            }
+            let (first, second) = reordered.split_at(reordered.len().div_ceil(2));
+            (0..reordered.len()).map(|i| if i % 2 == 0 { first[i / 2] } else { second[i / 2] }).collect()
+        }).collect()
+    }
+
+    #[test]
+    fn channels_are_sorted_and_compressed_losslessly() {
+        let (width, height) = (5, 20);
+        let colors = (0..width * height).map(|i| Vec3::new(i as f64, 0.5, -1.)).collect();
+        let colors = FloatImage::new(width, height, colors);
+        let depth: Vec<f64> = (0..width * height).map(|i| 1000. + i as f64 * 0.25).collect();
+
+        for compression in Compression::ALL.iter() {
+            let mut image = ExrImage::new(width, height, PixelType::Float, *compression);
+            image.add_color("", &colors);
+            image.add_channel("depth.Z", depth.clone());
+            let mut data = vec![];
+            image.write_image_data(&mut data).unwrap();
+
+            assert_eq!(&data[..4], &MAGIC);
+            let names = ["B", "G", "R", "depth.Z"];
+            let list: Vec<u8> = names.iter().flat_map(|name| {
+                let mut entry = name.as_bytes().to_vec();
+                entry.extend_from_slice(&[0, 2, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+                entry
+            }).collect();
+            assert!(data.windows(list.len()).any(|w| w == list.as_slice()), "{}", compression);
+
+            let lines = compression.lines_per_block();
+            let blocks = blocks(&data, height.div_ceil(lines), lines * width * 4 * 4);
+            let values: Vec<f32> = blocks.concat().chunks(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect();
+            for y in 0..height {
+                let line = &values[y * width * 4..(y + 1) * width * 4];
+                for x in 0..width {
+                    let i = y * width + x;
+                    assert_eq!(line[x], -1.);
+                    assert_eq!(line[width + x], 0.5);
+                    assert_eq!(line[2 * width + x], i as f32);
+                    assert_eq!(line[3 * width + x], depth[i] as f32);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::io::{self, BufRead, Read, Write};
+use crate::{
+    vec3::Vec3, 
+    image_formats::{self, FloatImage}
+};
+
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("bad Radiance HDR file: {}", message))
+}
+
+/*
+    Shared by every color of an RGBE pixel, the exponent is stored with a
+    bias of 128 and the mantissas are fractions of 256
+*/
+fn rgbe_to_color([r, g, b, e]: [u8; 4]) -> Vec3 {
+    if e == 0 {
+        return Vec3::ZERO;
+    }
+    let scale = 2f64.powi(e as i32 - (128 + 8));
+    Vec3::new(r as f64 * scale, g as f64 * scale, b as f64 * scale)
+}
+
+/*
+    The biggest of the colors picks the exponent, so that it keeps the 8 
+    bits of its mantissa. Negative colors become black
+*/
+fn color_to_rgbe(color: Vec3) -> [u8; 4] {
+    let biggest = color.x.max(color.y).max(color.z);
+    if biggest.is_nan() || biggest < 1e-32 {
+        return [0; 4];
+    }
+    // biggest = mantissa * 2^exponent with the mantissa in [0.5, 1)
+    let mut exponent = biggest.log2().floor() as i32 + 1;
+    if biggest / 2f64.powi(exponent) >= 1. {
+        exponent += 1;
+    }
+    if exponent > 127 {
+        return [255, 255, 255, 255];
+    }
+    let scale = 256. / 2f64.powi(exponent);
+    let mantissa = |c: f64| (c.max(0.) * scale) as u8;
+    [mantissa(color.x), mantissa(color.y), mantissa(color.z), (exponent + 128) as u8]
+}
+
+fn read_line(input: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(invalid_data("the header ends too early"));
+    }
+    Ok(line.trim_end_matches(&['\n', '\r'][..]).to_string())
+}
+
+/*
+    Reads one scanline of RGBE pixels. Since 1991 scanlines are usually
+    run-length encoded one channel at a time, older files store the pixels
+    as they are with some runs of repeated pixels
+*/
+fn read_scanline(input: &mut impl Read, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut start = [0u8; 4];
+    input.read_exact(&mut start)?;
+
+    let is_new_rle = (8..0x8000).contains(&width) && start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0;
+    if !is_new_rle {
+        return read_old_scanline(input, start, scanline);
+    }
+    if ((start[2] as usize) << 8 | start[3] as usize) != width {
+        return Err(invalid_data("a scanline has the wrong length"));
+    }
+
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            input.read_exact(&mut count)?;
+
+            // Above 128 it is a run of the same value, otherwise that many values follow
+            let (run, count) = match count[0] {
+                c if c > 128 => (true, c as usize - 128), 
+                c => (false, c as usize)
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid_data("a run goes past the end of its scanline"));
+            }
+
+            if run {
+                let mut value = [0u8; 1];
+                input.read_exact(&mut value)?;
+                scanline[x..x + count].iter_mut().for_each(|pixel| pixel[channel] = value[0]);
+            } else {
+                let mut values = [0u8; 128];
+                input.read_exact(&mut values[..count])?;
+                scanline[x..x + count].iter_mut().zip(&values).for_each(|(pixel, v)| pixel[channel] = *v);
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+fn read_old_scanline(input: &mut impl Read, first: [u8; 4], scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let mut pixel = first;
+    let mut x = 0;
+    // A run of 1, 1, 1 pixels repeats the previous pixel, consecutive runs
+    // make up the bigger digits of the count
+    let mut shift = 0;
+    loop {
+        if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 {
+            if x == 0 {
+                return Err(invalid_data("a scanline starts with a run"));
+            }
+            let count = (pixel[3] as usize) << shift;
+            if x + count > scanline.len() {
+                return Err(invalid_data("a run goes past the end of its scanline"));
+            }
+            let previous = scanline[x - 1];
+            scanline[x..x + count].iter_mut().for_each(|p| *p = previous);
+            x += count;
+            shift += 8;
+        } else {
+            scanline[x] = pixel;
+            x += 1;
+            shift = 0;
+        }
+
+        if x == scanline.len() {
+            return Ok(());
+        }
+        input.read_exact(&mut pixel)?;
+    }
+}
+
+/*
+    Reads a Radiance RGBE image as linear colors, row by row from the top
+*/
+pub fn read(mut input: impl BufRead) -> io::Result<FloatImage> {
+    let magic = read_line(&mut input)?;
+    if !magic.starts_with("#?") {
+        return Err(invalid_data("missing the #?RADIANCE signature"));
+    }
+
+    // Variables like FORMAT or EXPOSURE, up to an empty line
+    loop {
+        let line = read_line(&mut input)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid_data(&format!("unsupported pixel format {}", format)));
+            }
+        }
+    }
+
+    // Only the usual orientation is supported, rows from the top and
+    // pixels from the left
+    let resolution = read_line(&mut input)?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>().as_slice() {
+        ["-Y", height, "+X", width] => (height.parse::<usize>(), width.parse::<usize>()), 
+        _ => return Err(invalid_data(&format!("unsupported resolution line '{}'", resolution)))
+    };
+    let (height, width) = match (height, width) {
+        (Ok(height), Ok(width)) if height > 0 && width > 0 => (height, width), 
+        _ => return Err(invalid_data(&format!("bad resolution line '{}'", resolution)))
+    };
+
+    if image_formats::pixel_count(width, height).is_none() {
+        return Err(invalid_data(&format!("{}x{} is too big", width, height)));
+    }
+
+    // Grown row by row, a file shorter than its header says ends before 
+    // taking the memory it claims
+    let mut texels = vec![];
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(&mut input, &mut scanline)?;
+        texels.extend(scanline.iter().map(|pixel| rgbe_to_color(*pixel)));
+    }
+
+    Ok(FloatImage::new(width, height, texels))
+}
+
+/*
+    Saves 'image' as a Radiance RGBE image, with run-length encoded 
+    scanlines. Colors keep about 1% of precision, however bright
+*/
+pub fn write(image: &FloatImage, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", image.height(), image.width())?;
+
+    let width = image.width();
+    let mut data = vec![];
+    for row in image.pixels().chunks(width) {
+        let scanline: Vec<[u8; 4]> = row.iter().map(|color| color_to_rgbe(*color)).collect();
+        // Readers only take run-length encoded scanlines of these lengths
+        if !(8..0x8000).contains(&width) {
+            scanline.iter().for_each(|pixel| data.extend_from_slice(pixel));
+            continue;
+        }
+
+        data.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+        for channel in 0..4 {
+            let values: Vec<u8> = scanline.iter().map(|pixel| pixel[channel]).collect();
+            encode_runs(&values, &mut data);
+        }
+    }
+    out.write_all(&data)
+}
+
+/*
+    Runs of at least 4 equal values are stored as the count and the value, 
+    the values in between as they are after their count
+*/
+fn encode_runs(values: &[u8], out: &mut Vec<u8>) {
+    let run_length = |start: usize, longest: usize| {
+        values[start..].iter().take(longest).take_while(|v| **v == values[start]).count()
+    };
+
+    let mut x = 0;
+    while x < values.len() {
+        let run = run_length(x, 127);
+        if run >= 4 {
+            out.extend_from_slice(&[128 + run as u8, values[x]]);
+            x += run;
+            continue;
+        }
+
+        let mut end = x;
+        while end < values.len() && end - x < 128 && run_length(end, 4) < 4 {
+            end += 1;
+        }
+        out.push((end - x) as u8);
+        out.extend_from_slice(&values[x..end]);
+        x = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &[u8] = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=1.0\n\n";
+
+    #[test]
+    fn reads_run_length_encoded_scanlines() {
+        let mut file = HEADER.to_vec();
+        file.extend(b"-Y 1 +X 8\n");
+        file.extend(&[2, 2, 0, 8]);
+        // Red: a run of 8 times 128
+        file.extend(&[128 + 8, 128]);
+        // Green: 8 different values
+        file.extend(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        // Blue: two runs
+        file.extend(&[128 + 4, 0, 128 + 4, 64]);
+        // Exponent: 1 for the first half, 2 for the second
+        file.extend(&[128 + 4, 129, 128 + 4, 130]);
+
+        let image = read(file.as_slice()).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 1));
+        let texels = image.pixels();
+        assert_eq!(texels[0], Vec3::new(1., 0., 0.));
+        assert_eq!(texels[3], Vec3::new(1., 0.375, 0.));
+        assert_eq!(texels[7], Vec3::new(2., 1.75, 1.));
+    }
+
+    #[test]
+    fn reads_flat_scanlines() {
+        let mut file = HEADER.to_vec();
+        file.extend(b"-Y 2 +X 2\n");
+        file.extend(&[128, 64, 0, 129, 0, 0, 0, 0]);
+        // The second row repeats its first pixel with an old style run
+        file.extend(&[64, 64, 64, 128, 1, 1, 1, 1]);
+
+        let image = read(file.as_slice()).unwrap();
+        assert_eq!(image.pixels(), [
+            Vec3::new(1., 0.5, 0.), Vec3::ZERO, 
+            Vec3::from_float(0.25), Vec3::from_float(0.25)
+        ]);
+    }
+
+    #[test]
+    fn round_trips_within_the_precision_of_rgbe() {
+        for width in [3, 40].iter() {
+            let pixels = (0..width * 2).map(|i| match i % 5 {
+                // Runs of the same color
+                0 | 1 => Vec3::new(0.25, 1e3, 0.), 
+                2 => Vec3::new(i as f64 * 0.37, 1e-3, 5.), 
+                3 => Vec3::ZERO, 
+                _ => Vec3::new(-1., 0.5, 0.5)
+            }).collect();
+            let image = FloatImage::new(*width, 2, pixels);
+
+            let mut data = vec![];
+            write(&image, &mut data).unwrap();
+            let reloaded = read(data.as_slice()).unwrap();
+            assert_eq!((reloaded.width(), reloaded.height()), (*width, 2));
+            for (a, b) in reloaded.pixels().iter().zip(image.pixels()) {
+                let b = Vec3::new(b.x.max(0.), b.y, b.z);
+                let biggest = b.x.max(b.y).max(b.z);
+                assert!((*a - b).length() <= biggest / 128., "{:?} instead of {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let file = b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0";
+        assert!(read(&file[..]).is_err());
+        assert!(read(&b"P6\n1 1\n255\n"[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_huge_sizes() {
+        assert!(read(&b"#?RADIANCE\n\n-Y 18446744073709551615 +X 2\n"[..]).is_err());
+        assert!(read(&b"#?RADIANCE\n\n-Y 100000 +X 100000\n"[..]).is_err());
+    }
+}
@@ -1,7 +1,18 @@
 pub mod ppm;
 pub mod png;
+pub mod hdr;
+pub mod exr;
+pub mod pfm;
+pub mod tonemap;
 
-use std::io::Write;
+use crate::vec3::Vec3;
+use std::{
+    io::{self, Write}, 
+    fmt, 
+    fs::File, 
+    path::Path, 
+    str::FromStr
+};
 
 
 
@@ -11,4 +22,143 @@ pub trait Image {
 
     fn width(&self) -> usize;
     fn height(&self) -> usize;
-}
\ No newline at end of file
+}
+
+/*
+    A picture as the linear light the renderer found, row by row from the 
+    top. Values can go well above 1 for bright lights: they are kept as 
+    they are until the picture is saved, and only formats that cannot 
+    store them clamp them
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloatImage {
+    width: usize, 
+    height: usize, 
+    pixels: Vec<Vec3>
+}
+
+impl FloatImage {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>) -> FloatImage {
+        assert_eq!(pixels.len(), width * height, "a {}x{} image needs {} pixels", width, height, width * height);
+        FloatImage {width, height, pixels}
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+    pub fn pixels(&self) -> &[Vec3] { &self.pixels }
+    pub fn into_pixels(self) -> Vec<Vec3> { self.pixels }
+}
+
+// A 16384x16384 image, anything bigger read from a file is taken for a broken header
+const MAX_PIXELS: usize = 1 << 28;
+
+/*
+    The number of pixels of a 'width' x 'height' image found in the header 
+    of a file, None when it is too big to allocate
+*/
+pub fn pixel_count(width: usize, height: usize) -> Option<usize> {
+    width.checked_mul(height).filter(|count| *count <= MAX_PIXELS)
+}
+
+/*
+    Puts 'linear' in the 8 bit image 'image', which must be as big, with 
+    the colors 'transform' gives
+*/
+pub fn quantize(linear: &FloatImage, image: &mut impl Image, transform: &tonemap::DisplayTransform) {
+    assert_eq!((linear.width(), linear.height()), (image.width(), image.height()));
+    for (i, color) in linear.pixels().iter().enumerate() {
+        image.putpixel(i % linear.width(), i / linear.width(), transform.apply(*color));
+    }
+}
+
+/*
+    The image formats we can save to, named like their extension
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png, 
+    Ppm, 
+    Pfm, 
+    Hdr, 
+    Exr
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 5] = [
+        ImageFormat::Png, 
+        ImageFormat::Ppm, 
+        ImageFormat::Pfm, 
+        ImageFormat::Hdr, 
+        ImageFormat::Exr
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png", 
+            ImageFormat::Ppm => "ppm", 
+            ImageFormat::Pfm => "pfm", 
+            ImageFormat::Hdr => "hdr", 
+            ImageFormat::Exr => "exr"
+        }
+    }
+
+    // The format of the extension of 'path', in any case
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        extension.parse().ok()
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ImageFormat, String> {
+        ImageFormat::ALL.iter().copied().find(|format| format.name() == s).ok_or_else(|| {
+            let names: Vec<_> = ImageFormat::ALL.iter().map(|format| format.name()).collect();
+            format!("unknown image format '{}', expected one of {}", s, names.join(", "))
+        })
+    }
+}
+
+/*
+    Reads the image at 'path' as linear colors, row by row from the top, 
+    picking the format from the extension
+*/
+pub fn read_linear(path: &Path) -> Result<FloatImage, io::Error> {
+    match ImageFormat::from_path(path) {
+        Some(ImageFormat::Png) => png::read(io::BufReader::new(File::open(path)?)), 
+        Some(ImageFormat::Hdr) => hdr::read(io::BufReader::new(File::open(path)?)), 
+        Some(ImageFormat::Pfm) => pfm::read(io::BufReader::new(File::open(path)?)), 
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput, 
+            "unsupported image format, expected .png, .hdr or .pfm"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bright_colors_are_only_clamped_when_quantized() {
+        let linear = FloatImage::new(2, 1, vec![Vec3::new(4., 0.25, 0.), Vec3::new(-1., 1., 0.01)]);
+        assert_eq!(linear.pixels()[0].x, 4.);
+
+        let mut image = png::Png::new(2, 1);
+        quantize(&linear, &mut image, &tonemap::DisplayTransform::default());
+        let mut data = vec![];
+        image.write_image_data(&mut data).unwrap();
+        let colors = png::read(data.as_slice()).unwrap();
+        let expected = [Vec3::new(1., 0.25, 0.), Vec3::new(0., 1., 0.01)];
+        for (color, expected) in colors.pixels().iter().zip(expected.iter()) {
+            assert!((*color - *expected).length() < 0.01, "{:?} instead of {:?}", color, expected);
+        }
+    }
+}
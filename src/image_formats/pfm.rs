@@ -0,0 +1,132 @@
+use std::io::{self, BufRead, Read, Write};
+use crate::{
+    vec3::Vec3, 
+    image_formats::{self, FloatImage}
+};
+
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("bad PFM file: {}", message))
+}
+
+/*
+    The next word of the header, and the whitespace character after it
+*/
+fn read_word(input: &mut impl BufRead) -> io::Result<String> {
+    let mut word = vec![];
+    let mut byte = [0u8; 1];
+    loop {
+        if input.read(&mut byte)? == 0 {
+            return Err(invalid_data("the header ends too early"));
+        }
+        match (byte[0].is_ascii_whitespace(), word.is_empty()) {
+            (true, true) => continue, 
+            (true, false) => break, 
+            (false, _) => word.push(byte[0])
+        }
+    }
+    Ok(String::from_utf8_lossy(&word).to_string())
+}
+
+/*
+    Reads a Portable Float Map, color (PF) or grayscale (Pf), as linear
+    colors row by row from the top. The sign of the scale in the header
+    tells the byte order, its size is left out like most programs do
+*/
+pub fn read(mut input: impl BufRead) -> io::Result<FloatImage> {
+    let channels = match read_word(&mut input)?.as_str() {
+        "PF" => 3, 
+        "Pf" => 1, 
+        other => return Err(invalid_data(&format!("unknown signature '{}'", other)))
+    };
+    let width = read_word(&mut input)?.parse::<usize>().map_err(|_| invalid_data("bad width"))?;
+    let height = read_word(&mut input)?.parse::<usize>().map_err(|_| invalid_data("bad height"))?;
+    let scale = read_word(&mut input)?.parse::<f64>().map_err(|_| invalid_data("bad scale"))?;
+    if width == 0 || height == 0 || scale == 0. || scale.is_nan() {
+        return Err(invalid_data("the image is empty"));
+    }
+    let size = image_formats::pixel_count(width, height)
+        .ok_or_else(|| invalid_data(&format!("{}x{} is too big", width, height)))? * channels * 4;
+
+    // Read as it comes instead of all allocated up front, so a file 
+    // shorter than its header says doesn't take the memory it claims
+    let mut data = vec![];
+    input.take(size as u64).read_to_end(&mut data)?;
+    if data.len() < size {
+        return Err(invalid_data("the pixels end too early"));
+    }
+    let values: Vec<f64> = data.chunks_exact(4).map(|bytes| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match scale < 0. {
+            true => f32::from_le_bytes(bytes) as f64, 
+            false => f32::from_be_bytes(bytes) as f64
+        }
+    }).collect();
+
+    // The rows are stored from the bottom
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in values.chunks_exact(width * channels).rev() {
+        pixels.extend(row.chunks_exact(channels).map(|pixel| match pixel {
+            [r, g, b] => Vec3::new(*r, *g, *b), 
+            [l] => Vec3::from_float(*l), 
+            _ => unreachable!()
+        }));
+    }
+    Ok(FloatImage::new(width, height, pixels))
+}
+
+/*
+    Saves 'image' as a little endian color Portable Float Map, which keeps
+    the colors as 32 bit floats
+*/
+pub fn write(image: &FloatImage, out: &mut impl Write) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+
+    let mut data = Vec::with_capacity(image.pixels().len() * 12);
+    for row in image.pixels().chunks(image.width()).rev() {
+        for color in row {
+            for value in [color.x, color.y, color.z].iter() {
+                data.extend_from_slice(&(*value as f32).to_le_bytes());
+            }
+        }
+    }
+    out.write_all(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_as_floats() {
+        let pixels = vec![
+            Vec3::new(1e5, -2., 0.5), Vec3::new(0.1, 0., 3.), 
+            Vec3::ZERO, Vec3::from_float(0.25)
+        ];
+        let image = FloatImage::new(2, 2, pixels);
+
+        let mut data = vec![];
+        write(&image, &mut data).unwrap();
+        assert!(data.starts_with(b"PF\n2 2\n-1.0\n"));
+        let reloaded = read(data.as_slice()).unwrap();
+        assert_eq!((reloaded.width(), reloaded.height()), (2, 2));
+        for (a, b) in reloaded.pixels().iter().zip(image.pixels()) {
+            assert_eq!(*a, Vec3::new(b.x as f32 as f64, b.y as f32 as f64, b.z as f32 as f64));
+        }
+    }
+
+    #[test]
+    fn reads_big_endian_grayscale() {
+        let mut file = b"Pf 1 2 1.0\n".to_vec();
+        // The bottom row first
+        file.extend_from_slice(&2f32.to_be_bytes());
+        file.extend_from_slice(&0.5f32.to_be_bytes());
+
+        let image = read(file.as_slice()).unwrap();
+        assert_eq!(image.pixels(), [Vec3::from_float(0.5), Vec3::from_float(2.)]);
+        assert!(read(&file[..file.len() - 1]).is_err());
+        assert!(read(&b"P6\n1 1\n255\n"[..]).is_err());
+        assert!(read(&b"PF 18446744073709551615 2 -1.0\n"[..]).is_err());
+        assert!(read(&b"PF 100000 100000 -1.0\n"[..]).is_err());
+    }
+}
@@ -1,5 +1,8 @@
-use std::io::{Write, BufWriter};
-use crate::image_formats::Image;
+use std::io::{Read, Write, BufWriter};
+use crate::{
+    vec3::Vec3, 
+    image_formats::{Image, FloatImage, tonemap}
+};
 
 
 pub struct Png {
@@ -53,4 +56,30 @@ impl Image for Png {
 
     fn width(&self) -> usize { self.width }
     fn height(&self) -> usize { self.height }
-}
\ No newline at end of file
+}
+
+/*
+    Reads a PNG as linear colors, row by row from the top. The values are 
+    decoded with the sRGB curve they are saved with, so that an image 
+    saved by us without tone mapping reads back as the colors that were 
+    rendered
+*/
+pub fn read(input: impl Read) -> Result<FloatImage, std::io::Error> {
+    let mut decoder = png::Decoder::new(input);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info()?;
+
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+
+    let samples = info.color_type.samples();
+    let linear = |value: u8| tonemap::srgb_decode(value as f64 / 255.);
+    let texels = data.chunks_exact(samples).map(|pixel| match pixel {
+        [r, g, b, ..] => Vec3::new(linear(*r), linear(*g), linear(*b)), 
+        // Grayscale, with or without alpha
+        [l, ..] => Vec3::from_float(linear(*l)), 
+        [] => unreachable!()
+    }).collect();
+
+    Ok(FloatImage::new(info.width as usize, info.height as usize, texels))
+}
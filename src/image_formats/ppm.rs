@@ -6,17 +6,29 @@ pub struct PPMImage {
     width: usize, 
     height: usize, 
     max_color_value: usize, 
-    data: Vec::<u8>
+    data: Vec::<u8>, 
+    // Writes the numbers as text (P3) instead of bytes (P6)
+    ascii: bool
 }
 
 impl PPMImage {
     pub fn new(width: usize, height: usize) -> PPMImage {
         PPMImage {
             max_color_value: 255, 
-            width, height, data: vec![0; width*height*3]
+            width, height, data: vec![0; width*height*3], 
+            ascii: false
         }
     }
 
+    /*
+        The text version is about 4 times bigger, but can be read and 
+        edited by hand
+    */
+    pub fn with_ascii(mut self, ascii: bool) -> PPMImage {
+        self.ascii = ascii;
+        self
+    }
+
     fn offset(&self, x: usize, y: usize) -> usize {
         3 * (self.width * y + x)
     }
@@ -39,14 +51,20 @@ impl Image for PPMImage {
         let mut stream = BufWriter::new(out);
 
         let mut written_bytes = 0;
-        written_bytes += stream.write(b"P3\n")?;
+        written_bytes += stream.write(if self.ascii { b"P3\n" } else { b"P6\n" })?;
         written_bytes += stream.write(self.width.to_string().as_bytes())?;
         written_bytes += stream.write(b" ")?;
         written_bytes += stream.write(self.height.to_string().as_bytes())?;
         written_bytes += stream.write(b"\n")?;
         written_bytes += stream.write(self.max_color_value.to_string().as_bytes())?;
         written_bytes += stream.write(b"\n")?;
-        
+
+        if !self.ascii {
+            stream.write_all(&self.data)?;
+            stream.flush()?;
+            return Ok(written_bytes + self.data.len());
+        }
+
         let mut i = 0;
         while i < self.data.len() {
             for _ in 0..3*(self.width-1) {
@@ -66,9 +84,34 @@ impl Image for PPMImage {
             i += 3;
         }
 
+        stream.flush()?;
         Ok(written_bytes)
     }
 
     fn height(&self) -> usize { self.height }
     fn width(&self) -> usize { self.width }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(image: &PPMImage) -> Vec<u8> {
+        let mut data = vec![];
+        let size = image.write_image_data(&mut data).unwrap();
+        assert_eq!(size, data.len());
+        data
+    }
+
+    #[test]
+    fn binary_and_ascii() {
+        let mut image = PPMImage::new(2, 1);
+        image.putpixel(0, 0, (255, 0, 10));
+        image.putpixel(1, 0, (1, 2, 3));
+        assert!(!image.putpixel(2, 0, (0, 0, 0)));
+
+        assert_eq!(written(&image), b"P6\n2 1\n255\n\xff\x00\x0a\x01\x02\x03".to_vec());
+        let image = image.with_ascii(true);
+        assert_eq!(written(&image), b"P3\n2 1\n255\n255 0 10 1 2 3\n".to_vec());
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    fmt, 
+    str::FromStr
+};
+use crate::vec3::Vec3;
+
+
+/*
+    How the light of a pixel, which can be far brighter than 1, is brought
+    into the range of colors a screen shows
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapper {
+    // Everything above 1 is white, bright parts lose their details
+    Clamp, 
+    // Reinhard's global operator: colors as bright as the white point become white
+    Reinhard, 
+    // John Hable's filmic curve from Uncharted 2
+    Hable, 
+    // Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces
+}
+
+impl ToneMapper {
+    pub const ALL: [ToneMapper; 4] = [
+        ToneMapper::Clamp, 
+        ToneMapper::Reinhard, 
+        ToneMapper::Hable, 
+        ToneMapper::Aces
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapper::Clamp => "clamp", 
+            ToneMapper::Reinhard => "reinhard", 
+            ToneMapper::Hable => "hable", 
+            ToneMapper::Aces => "aces"
+        }
+    }
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ToneMapper, String> {
+        ToneMapper::ALL.iter().copied().find(|kind| kind.name() == s).ok_or_else(|| {
+            let names: Vec<_> = ToneMapper::ALL.iter().map(|kind| kind.name()).collect();
+            format!("unknown tone mapper '{}', expected one of {}", s, names.join(", "))
+        })
+    }
+}
+
+/*
+    Turns linear light into the colors of an 8 bit image: scaled by
+    2^'exposure', tone mapped and encoded with the sRGB curve.
+    'white_point' is the brightness Reinhard maps to white, infinite
+    for the plain operator that never quite gets there
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayTransform {
+    pub tone_mapper: ToneMapper, 
+    pub exposure: f64, 
+    pub white_point: f64
+}
+
+impl Default for DisplayTransform {
+    fn default() -> DisplayTransform {
+        DisplayTransform {tone_mapper: ToneMapper::Clamp, exposure: 0., white_point: f64::INFINITY}
+    }
+}
+
+impl DisplayTransform {
+    pub fn apply(&self, color: Vec3) -> (u8, u8, u8) {
+        let color = self.tone_map(color * 2f64.powf(self.exposure));
+        let encode = |c: f64| (255. * srgb_encode(c.clamp(0., 1.)) + 0.5) as u8;
+        (encode(color.x), encode(color.y), encode(color.z))
+    }
+
+    // The color in [0, 1] shown for 'color'
+    pub fn tone_map(&self, color: Vec3) -> Vec3 {
+        let color = color.max(&Vec3::ZERO);
+        match self.tone_mapper {
+            ToneMapper::Clamp => color, 
+            ToneMapper::Reinhard => {
+                // Scaling all the channels by the same amount keeps the hue
+                let luminance = color.luminance();
+                if luminance <= 0. {
+                    return Vec3::ZERO;
+                }
+                let white = self.white_point * self.white_point;
+                let mapped = luminance * (1. + luminance / white) / (1. + luminance);
+                color * (mapped / luminance)
+            }, 
+            ToneMapper::Hable => {
+                const EXPOSURE_BIAS: f64 = 2.;
+                const WHITE: f64 = 11.2;
+                let white_scale = 1. / hable(WHITE);
+                let map = |c: f64| hable(c * EXPOSURE_BIAS) * white_scale;
+                Vec3::new(map(color.x), map(color.y), map(color.z))
+            }, 
+            ToneMapper::Aces => {
+                // The fit expects colors 0.6 times as bright as the ones it was made on
+                let map = |c: f64| {
+                    let c = c * 0.6;
+                    (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+                };
+                Vec3::new(map(color.x), map(color.y), map(color.z))
+            }
+        }.max(&Vec3::ZERO).min(&Vec3::ONE)
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15; // Shoulder strength
+    const B: f64 = 0.50; // Linear strength
+    const C: f64 = 0.10; // Linear angle
+    const D: f64 = 0.20; // Toe strength
+    const E: f64 = 0.02; // Toe numerator
+    const F: f64 = 0.30; // Toe denominator
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/*
+    The sRGB transfer function, from linear light in [0, 1] to the value
+    stored in the image: a straight line near black and a 2.4 power curve
+    above
+*/
+pub fn srgb_encode(c: f64) -> f64 {
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+// The inverse of srgb_encode
+pub fn srgb_decode(v: f64) -> f64 {
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trips() {
+        for i in 0..=100 {
+            let c = i as f64 / 100.;
+            assert!((srgb_decode(srgb_encode(c)) - c).abs() < 1e-12);
+        }
+        assert!((srgb_encode(0.5) - 0.735_356_983).abs() < 1e-6);
+        assert_eq!(DisplayTransform::default().apply(Vec3::new(0., 0.5, 1.)), (0, 188, 255));
+    }
+
+    #[test]
+    fn tone_mappers_keep_highlights() {
+        for tone_mapper in ToneMapper::ALL.iter() {
+            let transform = DisplayTransform {tone_mapper: *tone_mapper, ..DisplayTransform::default()};
+            let map = |c| transform.tone_map(Vec3::from_float(c)).x;
+            assert_eq!(map(0.), 0., "{}", tone_mapper);
+            assert_eq!(map(-1.), 0., "{}", tone_mapper);
+            // Brighter is never darker
+            let values: Vec<f64> = (0..200).map(|i| map(i as f64 * 0.1)).collect();
+            assert!(values.windows(2).all(|w| w[0] <= w[1]), "{}", tone_mapper);
+            // Except for the clamp, bright lights still differ from less bright ones
+            if *tone_mapper != ToneMapper::Clamp {
+                assert!(map(2.) < map(4.) && map(4.) < 1., "{}", tone_mapper);
+            }
+        }
+    }
+
+    #[test]
+    fn reinhard_white_point_is_white() {
+        let transform = DisplayTransform {tone_mapper: ToneMapper::Reinhard, white_point: 4., ..DisplayTransform::default()};
+        assert!((transform.tone_map(Vec3::from_float(4.)).y - 1.).abs() < 1e-12);
+        // The plain operator only gets close
+        let plain = DisplayTransform {tone_mapper: ToneMapper::Reinhard, ..DisplayTransform::default()};
+        assert!((plain.tone_map(Vec3::from_float(4.)).y - 0.8).abs() < 1e-12);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let transform = DisplayTransform {exposure: -1., ..DisplayTransform::default()};
+        assert_eq!(transform.apply(Vec3::from_float(1.)), DisplayTransform::default().apply(Vec3::from_float(0.5)));
+    }
+}
@@ -1,24 +1,39 @@
 mod raytracing;
 mod vec3;
 mod image_formats;
+mod scene;
+mod model_formats;
+mod checkpoint;
 
-use std::fs::File;
+use std::{
+    fs::File, 
+    io::{self, BufWriter, Write}, 
+    path::Path
+};
 use vec3::Vec3;
 use std::sync::Arc;
 use indicatif::{ProgressBar, ProgressStyle};
-use image_formats::Image;
+use image_formats::{Image, ImageFormat};
 use raytracing::{
     material, 
     Material, 
+    Hittable, 
     hittable
 };
-use clap::Clap;
+use clap::{Clap, ArgEnum};
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
+use std::time::{Duration, Instant};
 
 
-fn cool_picture_world() -> Vec<Box<dyn hittable::Hittable + Send + Sync>> {
+/*
+    The scene on the cover of the book: three big spheres surrounded by 
+    small random ones placed on a grid that goes from -grid_size to grid_size 
+    on both axes of the ground plane
+*/
+fn cool_picture_world(grid_size: i32, rng: &mut dyn RngCore) -> Vec<Box<dyn hittable::Hittable + Send + Sync>> {
     let mut world: Vec<Box<dyn hittable::Hittable + Send + Sync>> = vec![];
 
-    let ground_material: Arc<Box<(dyn Material + Send + Sync + 'static)>> = Arc::new(Box::new(
+    let ground_material: Arc<Box<dyn Material + Send + Sync + 'static >> = Arc::new(Box::new(
         material::Lambertian::new(Vec3::new(0.5, 0.5, 0.5))
     ));
     world.push(Box::new(hittable::Sphere::new(
@@ -27,23 +42,23 @@ fn cool_picture_world() -> Vec<Box<dyn hittable::Hittable + Send + Sync>> {
         ground_material.clone()
     )));
 
-    let rand_val = |min, max| min + (max - min) * rand::random::<f64>();
-    let rand_vec = |min, max| Vec3::new(rand_val(min, max), rand_val(min, max), rand_val(min, max));
+    let rand_val = |rng: &mut dyn RngCore, min: f64, max: f64| min + (max - min) * rng.gen::<f64>();
+    let rand_vec = |rng: &mut dyn RngCore, min, max| Vec3::new(rand_val(rng, min, max), rand_val(rng, min, max), rand_val(rng, min, max));
 
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = rand_val(-1., 1.);
-            let center = Vec3::new(a as f64 + 0.9 * rand_val(-1., 1.), 0.2, b as f64 + 0.9 * rand_val(-1., 1.));
+    for a in -grid_size..grid_size {
+        for b in -grid_size..grid_size {
+            let choose_mat = rand_val(rng, -1., 1.);
+            let center = Vec3::new(a as f64 + 0.9 * rand_val(rng, -1., 1.), 0.2, b as f64 + 0.9 * rand_val(rng, -1., 1.));
 
             if (center - Vec3::new(4.0, 0.2, 0.)).length() > 0.9 {
                 let sphere_material: Arc<Box<dyn Material + Send + Sync>> = {
                     if choose_mat < 0.8 {
                         // Diffuse
-                        Arc::new(Box::new(material::Lambertian::new(rand_vec(-1., 1.) * rand_vec(-1., 1.))))
+                        Arc::new(Box::new(material::Lambertian::new(rand_vec(rng, -1., 1.) * rand_vec(rng, -1., 1.))))
                     } else if choose_mat < 0.95 {
                         // Metal
-                        let albedo = rand_vec(0.5, 1.0);
-                        let fuzz = rand_val(0., 0.5);
+                        let albedo = rand_vec(rng, 0.5, 1.0);
+                        let fuzz = rand_val(rng, 0., 0.5);
                         Arc::new(Box::new(material::Metal::new(albedo, fuzz)))
                     } else {
                         // Glass
@@ -81,64 +96,432 @@ fn cool_picture_world() -> Vec<Box<dyn hittable::Hittable + Send + Sync>> {
     world
 }
 
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq)]
+enum BvhKind {
+    Sah, 
+    Median
+}
+
 #[derive(Clap, Debug)]
 #[clap(version = "1.0", author = "Marco C. <marco.cutecchia@outlook.it>")]
 struct Options {
+    // Where the image is saved, - writes it to the standard output
     #[clap(short, long, default_value="image.png")]
     output_name: String, 
+    // png, ppm, pfm, hdr or exr, by default picked from the extension 
+    // of the output name
+    #[clap(long)]
+    format: Option<ImageFormat>, 
+    // Saves .ppm images as text instead of bytes
+    #[clap(long)]
+    ppm_ascii: bool, 
     #[clap(short, long, default_value="640")]
     width: usize, 
     #[clap(short, long, default_value="480")]
     height: usize, 
     #[clap(short, long, default_value="50")]
-    samples_per_pixels: i32, 
+    samples_per_pixels: u32, 
     #[clap(short, long, default_value="20.0")]
-    vertical_fov: f64
+    vertical_fov: f64, 
+    #[clap(long, arg_enum, default_value="sah")]
+    bvh: BvhKind, 
+    #[clap(long, default_value="11")]
+    grid_size: i32, 
+    #[clap(long)]
+    scene: Option<String>, 
+    #[clap(long)]
+    export_scene: Option<String>, 
+    #[clap(long)]
+    model: Option<String>, 
+    // black, gradient, a color like 0.1,0.2,0.3 or the path to an image, 
+    // replaces the background of the scene
+    #[clap(long)]
+    background: Option<scene::BackgroundDescription>, 
+    // The most bounces a path can make, replaces the one of the scene
+    #[clap(long)]
+    max_depth: Option<u32>, 
+    // Bounces after which paths carrying little light can be stopped
+    #[clap(long)]
+    roulette_depth: Option<u32>, 
+    // Renders with the same seed give the same image, also picks the 
+    // random scene
+    #[clap(long)]
+    seed: Option<u64>, 
+    // How the random numbers of the samples of a pixel are spread: 
+    // independent, stratified, halton or sobol
+    #[clap(long, default_value="sobol")]
+    sampler: raytracing::SamplerKind, 
+    // Lets pixels stop taking samples once their error goes below this, 
+    // for example 0.005. Samples per pixel is then the most they can take
+    #[clap(long)]
+    adaptive_threshold: Option<f64>, 
+    // The samples every pixel takes before it can stop with adaptive sampling
+    #[clap(long, default_value="16")]
+    min_samples: u32, 
+    // Saves an image of how many samples each pixel took
+    #[clap(long)]
+    heatmap: Option<String>, 
+    // Renders in passes of this many samples per pixel over the whole 
+    // image, saving it after each one
+    #[clap(long)]
+    pass_samples: Option<u32>, 
+    // Keeps rendering passes until this much time has gone, like 90s, 
+    // 10m or 1.5h, instead of stopping at the samples per pixel
+    #[clap(long, parse(try_from_str = parse_duration))]
+    time_limit: Option<Duration>, 
+    // Saves what was rendered so far to this file after every pass
+    #[clap(long)]
+    checkpoint: Option<String>, 
+    // Goes on with the render saved in this checkpoint, and keeps saving to it
+    #[clap(long)]
+    resume: Option<String>, 
+    // How .exr images store their values: half or float
+    #[clap(long, default_value="half")]
+    exr_pixel_type: image_formats::exr::PixelType, 
+    // How .exr images are compressed: none or zip
+    #[clap(long, default_value="zip")]
+    exr_compression: image_formats::exr::Compression, 
+    // How bright colors are shown in 8 bit images: clamp, reinhard, hable or aces
+    #[clap(long, default_value="clamp")]
+    tone_mapper: image_formats::tonemap::ToneMapper, 
+    // Makes 8 bit images brighter or darker by this many stops
+    #[clap(long, default_value="0", allow_hyphen_values = true)]
+    exposure: f64, 
+    // The brightness reinhard shows as white, by default nothing quite is
+    #[clap(long)]
+    white_point: Option<f64>
 }
 
-fn main() { 
-    const APERTURE: f64 = 0.1;
-    const DIST_TO_FOCUS: f64 = 10.0;
+/*
+    A number of seconds, or of minutes or hours when followed by m or h
+*/
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, 'h')) => (&s[..i], 3600.), 
+        Some((i, 'm')) => (&s[..i], 60.), 
+        Some((i, 's')) => (&s[..i], 1.), 
+        _ => (s, 1.)
+    };
+    // Negative, infinite and too long durations are errors too
+    number.parse::<f64>().ok()
+        .and_then(|n| Duration::try_from_secs_f64(n * unit).ok())
+        .ok_or_else(|| format!("'{}' is not a duration like 90s, 10m or 1.5h", s))
+}
+
+/*
+    Writes next to 'path' first and then moves the file over it, so that 
+    programs showing the image never find it half written. When saving 
+    fails the partial file is removed
+*/
+fn save_file(path: &str, write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>) -> io::Result<()> {
+    let partial = format!("{}.part", path);
+    let mut out = BufWriter::new(File::create(&partial)?);
+    let result = write(&mut out).and_then(|_| out.flush());
+    drop(out);
+    let result = result.and_then(|_| std::fs::rename(&partial, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/*
+    Writes what was rendered so far in 'format': .exr keeps the linear 
+    colors with the normal, depth and albedo layers, .hdr and .pfm only 
+    the colors, .png and .ppm are 8 bit images made with the tone mapper
+*/
+fn write_image(film: &raytracing::Film, opt: &Options, format: ImageFormat, out: &mut impl Write) -> io::Result<()> {
+    let transform = image_formats::tonemap::DisplayTransform {
+        tone_mapper: opt.tone_mapper, 
+        exposure: opt.exposure, 
+        white_point: opt.white_point.unwrap_or(f64::INFINITY)
+    };
 
+    match format {
+        ImageFormat::Exr => {
+            let mut image = image_formats::exr::ExrImage::new(film.width(), film.height(), opt.exr_pixel_type, opt.exr_compression);
+            image.add_color("", &film.image());
+            image.add_directions("normal", &film.normals());
+            image.add_channel("depth.Z", film.depth());
+            image.add_color("albedo", &film.albedo());
+            image.write_image_data(out)
+        }, 
+        ImageFormat::Hdr => image_formats::hdr::write(&film.image(), out), 
+        ImageFormat::Pfm => image_formats::pfm::write(&film.image(), out), 
+        ImageFormat::Png => {
+            let mut image = image_formats::png::Png::new(film.width(), film.height());
+            image_formats::quantize(&film.image(), &mut image, &transform);
+            image.write_image_data(out).map(|_| ())
+        }, 
+        ImageFormat::Ppm => {
+            let mut image = image_formats::ppm::PPMImage::new(film.width(), film.height()).with_ascii(opt.ppm_ascii);
+            image_formats::quantize(&film.image(), &mut image, &transform);
+            image.write_image_data(out).map(|_| ())
+        }
+    }
+}
+
+fn main() { 
     let opt = Options::parse();
-    println!("{:?}", opt);
+    eprintln!("{:?}", opt);
 
-    let look_from = Vec3::new(13.0, 2.0, 3.0);
-    let look_at = Vec3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
+    // The standard output only gets the image, messages go to the standard error
+    let to_stdout = opt.output_name == "-";
+    let format = match (opt.format, to_stdout) {
+        (Some(format), _) => Some(format), 
+        (None, true) => Some(ImageFormat::Png), 
+        (None, false) => ImageFormat::from_path(Path::new(&opt.output_name))
+    };
+    let format = format.unwrap_or_else(|| {
+        eprintln!("Cannot tell the format of {} from its extension, expected .png, .ppm, .pfm, .hdr or .exr or a --format", opt.output_name);
+        std::process::exit(1);
+    });
+
+    let model = opt.model.as_ref().map(|path| {
+        let meshes = model_formats::load(Path::new(path), None).unwrap_or_else(|e| {
+            eprintln!("Failed to load model {}: {}", path, e);
+            std::process::exit(1);
+        });
+        meshes.into_iter().flat_map(|mesh| mesh.into_hittables()).collect::<Vec<_>>()
+    });
+
+    let mut scene = match (&opt.scene, model) {
+        (Some(path), model) => {
+            let mut scene = scene::load(Path::new(path)).unwrap_or_else(|e| {
+                eprintln!("Failed to load scene {}: {}", path, e);
+                std::process::exit(1);
+            });
+            scene.objects.extend(model.into_iter().flatten());
+            scene
+        }, 
+        // Without a scene the model is shown alone, in front of the camera
+        (None, Some(model)) => scene::Scene {
+            camera: scene::CameraDescription::framing(&model.bounding_box(), opt.vertical_fov), 
+            background: scene::BackgroundDescription::default(), 
+            render: scene::RenderDescription::default(), 
+            objects: model
+        }, 
+        (None, None) => scene::Scene {
+            camera: scene::CameraDescription {
+                look_from: [13.0, 2.0, 3.0], 
+                look_at: [0.0, 0.0, 0.0], 
+                vup: [0.0, 1.0, 0.0], 
+                vertical_fov: opt.vertical_fov, 
+                aperture: 0.1, 
+                focus_distance: Some(10.0)
+            }, 
+            background: scene::BackgroundDescription::default(), 
+            render: scene::RenderDescription::default(), 
+            objects: cool_picture_world(opt.grid_size, &mut StdRng::seed_from_u64(opt.seed.unwrap_or(0)))
+        }
+    };
+
+    if let Some(background) = opt.background.clone() {
+        scene.background = background;
+    }
+    if opt.max_depth.is_some() {
+        scene.render.max_depth = opt.max_depth;
+    }
+    if opt.roulette_depth.is_some() {
+        scene.render.roulette_depth = opt.roulette_depth;
+    }
+    if opt.seed.is_some() {
+        scene.render.seed = opt.seed;
+    }
+    let mut settings = raytracing::RenderSettings {
+        sampler: opt.sampler, 
+        samples_per_pixel: opt.samples_per_pixels, 
+        adaptive: opt.adaptive_threshold.map(|threshold| raytracing::AdaptiveSampling {
+            min_samples: opt.min_samples, 
+            threshold
+        }), 
+        ..scene.render.to_settings()
+    };
+
+    let background = scene.background.build().unwrap_or_else(|e| {
+        eprintln!("Failed to load the background: {}", e);
+        std::process::exit(1);
+    });
+
+    if let Some(path) = &opt.export_scene {
+        if let Err(e) = scene.save(Path::new(path)) {
+            eprintln!("Failed to export scene to {}: {}", path, e);
+            std::process::exit(1);
+        }
+        eprintln!("Scene saved to {}", path);
+    }
 
     let aspect_ratio = opt.width as f64 / opt.height as f64;
+    let camera = scene.camera.to_camera(aspect_ratio);
+
+    let checkpoint_path = opt.checkpoint.as_ref().or(opt.resume.as_ref());
+    let scene_fingerprint = checkpoint_path.map(|_| scene.fingerprint().unwrap_or_else(|e| {
+        eprintln!("Failed to describe the scene for the checkpoint: {}", e);
+        std::process::exit(1);
+    }));
 
-    let camera = raytracing::Camera::new(
-        &look_from, &look_at, &vup, 
-        opt.vertical_fov, 
-        aspect_ratio, 
-        APERTURE, 
-        DIST_TO_FOCUS
-    );
-    let world = cool_picture_world();
-    let mut image = image_formats::png::Png::new(opt.width, opt.height);
-
-    let progressbar = Box::new(ProgressBar::new(image.height() as u64));
-    progressbar.set_style(ProgressStyle::default_bar()
-        .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} lines rendered ({eta})")
-        .progress_chars("#>-")
-    );
-
-    let position = Box::new(std::sync::Mutex::new(0));
-    raytracing::draw_world_with_callback(
-        &camera, 
-        &world, 
-        &mut image, 
-        opt.samples_per_pixels, 
-        move || {
-            let mut p = position.lock().unwrap();
-            *p += 1;
-            progressbar.set_position(*p);
+    let objects = scene.objects;
+    let object_count = objects.len();
+    let lights = raytracing::LightList::from_objects(&objects);
+    if !lights.is_empty() {
+        eprintln!("{} objects emit light", lights.len());
+    }
+    let build_start = Instant::now();
+    let bvh: Box<dyn hittable::Hittable + Send + Sync> = match opt.bvh {
+        BvhKind::Sah => {
+            let bvh = hittable::LinearBvh::new(objects);
+            eprintln!("BVH: {}", bvh.stats());
+            Box::new(bvh)
+        }, 
+        BvhKind::Median => Box::new(hittable::BvhNode::new(objects))
+    };
+    eprintln!("Built BVH over {} objects in {:.2?}", object_count, build_start.elapsed());
+    let world = raytracing::World {objects: bvh, background, lights};
+
+    let mut film = raytracing::Film::new(opt.width, opt.height);
+    if let Some(path) = &opt.resume {
+        let (checkpoint, checkpoint_film) = checkpoint::Checkpoint::load(Path::new(path), opt.width, opt.height).unwrap_or_else(|e| {
+            eprintln!("Cannot resume from {}: {}", path, e);
+            std::process::exit(1);
+        });
+        if Some(checkpoint.scene_fingerprint) != scene_fingerprint {
+            eprintln!("Cannot resume from {}: the scene changed since it was saved", path);
+            std::process::exit(1);
+        }
+
+        // The new samples go on from the ones in the checkpoint
+        settings.seed = checkpoint.seed;
+        settings.sampler = checkpoint.sampler;
+        film = checkpoint_film;
+        let samples = film.pixels().iter().map(|pixel| pixel.stats.count()).max().unwrap_or(0);
+        eprintln!("Resuming from {} with {} samples per pixel", path, samples);
+    }
+
+    /*
+        With a time limit there is no knowing how many passes there will be: 
+        the progress bar shows the time instead. A checkpoint is only saved 
+        between passes, so then they are kept short too
+    */
+    let default_pass_samples = match (opt.time_limit, &opt.checkpoint) {
+        (Some(_), _) => 4, 
+        (None, Some(_)) => 16, 
+        (None, None) => opt.samples_per_pixels
+    };
+    let pass_samples = opt.pass_samples.unwrap_or(default_pass_samples).max(1);
+    let mut samples_taken = film.pixels().iter().map(|pixel| pixel.stats.count()).max().unwrap_or(0);
+    let passes = opt.samples_per_pixels.saturating_sub(samples_taken).div_ceil(pass_samples).max(1);
+    let progressbar = match opt.time_limit {
+        None => {
+            let progressbar = ProgressBar::new((film.height() * passes as usize) as u64);
+            progressbar.set_style(ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} lines rendered ({eta})")
+                .progress_chars("#>-")
+            );
+            progressbar
+        }, 
+        Some(limit) => {
+            let progressbar = ProgressBar::new(limit.as_millis() as u64);
+            progressbar.set_style(ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] [{bar:40.cyan/blue}] {msg}")
+                .progress_chars("#>-")
+            );
+            progressbar
+        }
+    };
+
+    let render_start = Instant::now();
+    let on_row_render = || match opt.time_limit {
+        None => progressbar.inc(1), 
+        Some(_) => progressbar.set_position(render_start.elapsed().as_millis() as u64)
+    };
+    let mut total_samples = film.sample_counts().iter().map(|count| *count as u64).sum::<u64>();
+    for pass in 1.. {
+        let pass_start = Instant::now();
+        let samples = match opt.time_limit {
+            None => pass_samples.min(opt.samples_per_pixels.saturating_sub(samples_taken)), 
+            Some(_) => pass_samples
+        };
+        raytracing::render_pass(&camera, &world, &settings, &mut film, samples, &on_row_render);
+        samples_taken += samples;
+
+        // The standard output gets the image once, at the end
+        if !to_stdout {
+            if let Err(e) = save_file(&opt.output_name, |out| write_image(&film, &opt, format, out)) {
+                eprintln!("Failed to save the image to {}: {}", opt.output_name, e);
+                std::process::exit(1);
+            }
+        }
+        if let (Some(path), Some(scene_fingerprint)) = (checkpoint_path, scene_fingerprint) {
+            let checkpoint = checkpoint::Checkpoint {seed: settings.seed, sampler: settings.sampler, scene_fingerprint};
+            if let Err(e) = checkpoint.save(&film, Path::new(path)) {
+                eprintln!("Failed to save the checkpoint to {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+
+        let message = match opt.time_limit {
+            None => format!("Pass {}/{}: {} samples per pixel", pass, passes, samples_taken), 
+            Some(_) => format!("Pass {}: {} samples per pixel", pass, samples_taken)
+        };
+        let message = if to_stdout { message } else { format!("{}, saved to {}", message, opt.output_name) };
+        if passes > 1 || opt.time_limit.is_some() {
+            progressbar.set_message(&format!("{} samples per pixel", samples_taken));
+            // Without a terminal the progress bar is hidden and prints nothing
+            if progressbar.is_hidden() { eprintln!("{}", message) } else { progressbar.println(message) }
         }
-    );
 
-    let mut out = File::create(opt.output_name).expect("Failed to create file");
-    image.write_image_data(&mut out).expect("Failed to write to stdout");
-    println!("Done!");
+        // Stops early when adaptive sampling finds every pixel good enough
+        let previous_total = total_samples;
+        total_samples = film.sample_counts().iter().map(|count| *count as u64).sum::<u64>();
+        let finished = match opt.time_limit {
+            None => pass >= passes, 
+            // Another pass like this one would go past the limit
+            Some(limit) => render_start.elapsed() + pass_start.elapsed() > limit
+        };
+        if finished || total_samples == previous_total {
+            break;
+        }
+    }
+    progressbar.finish();
+    if to_stdout {
+        let stdout = io::stdout();
+        let mut out = BufWriter::new(stdout.lock());
+        if let Err(e) = write_image(&film, &opt, format, &mut out).and_then(|_| out.flush()) {
+            eprintln!("Failed to write the image: {}", e);
+            std::process::exit(1);
+        }
+    }
+    eprintln!("Done in {:.2?}!", render_start.elapsed());
+
+    let sample_counts = film.sample_counts();
+    if settings.adaptive.is_some() || opt.time_limit.is_some() {
+        eprintln!("{:.1} samples per pixel on average", total_samples as f64 / sample_counts.len() as f64);
+    }
+    if let Some(path) = &opt.heatmap {
+        let mut heatmap = image_formats::png::Png::new(opt.width, opt.height);
+        raytracing::adaptive::draw_heatmap(&sample_counts, &mut heatmap);
+        if let Err(e) = save_file(path, |out| heatmap.write_image_data(out).map(|_| ())) {
+            eprintln!("Failed to save the heatmap to {}: {}", path, e);
+            std::process::exit(1);
+        }
+        eprintln!("Samples per pixel saved to {}", path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("-1m").is_err());
+        assert!(parse_duration("1e20h").is_err());
+        assert!(parse_duration("inf").is_err());
+    }
 }
\ No newline at end of file
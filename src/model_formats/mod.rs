@@ -0,0 +1,78 @@
+pub mod obj;
+pub mod ply;
+
+use crate::raytracing::{
+    Material, 
+    hittable::TriangleMesh
+};
+use std::{
+    fmt, 
+    path::Path, 
+    sync::Arc
+};
+
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(std::io::Error), 
+    UnsupportedFormat(String), 
+    Parse { line: usize, message: String }, 
+    Invalid(String)
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::Io(e) => write!(f, "{}", e), 
+            ModelError::UnsupportedFormat(extension) => write!(
+                f, "unsupported model format '{}', expected .obj or .ply", extension
+            ), 
+            ModelError::Parse { line, message } => write!(f, "line {}: {}", line, message), 
+            ModelError::Invalid(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+impl From<std::io::Error> for ModelError {
+    fn from(e: std::io::Error) -> ModelError { ModelError::Io(e) }
+}
+
+/*
+    Loads the meshes in a model file, picking the format from the extension. 
+    When material is given every mesh uses it instead of the materials 
+    described by the file. A model without triangles is an error, there 
+    would be nothing to render or to point the camera at
+*/
+pub fn load(path: &Path, material: Option<Arc<Box<dyn Material + Send + Sync>>>) -> Result<Vec<TriangleMesh>, ModelError> {
+    let format = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+
+    let meshes = match format.as_str() {
+        "obj" => obj::load(path, material)?, 
+        "ply" => ply::load(path, material)?, 
+        other => return Err(ModelError::UnsupportedFormat(other.to_string()))
+    };
+    non_empty(meshes)
+}
+
+fn non_empty(meshes: Vec<TriangleMesh>) -> Result<Vec<TriangleMesh>, ModelError> {
+    if meshes.iter().all(|mesh| mesh.triangle_count() == 0) {
+        return Err(ModelError::Invalid("the model has no faces".to_string()));
+    }
+    Ok(meshes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_models_are_errors() {
+        let no_mtl = |_: &str| Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no material libraries in tests"));
+        let points = obj::parse("v 0 0 0\nv 1 0 0", no_mtl, None).unwrap();
+        assert!(non_empty(points).is_err());
+        let triangle = obj::parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3", no_mtl, None).unwrap();
+        assert_eq!(non_empty(triangle).unwrap().len(), 1);
+    }
+}
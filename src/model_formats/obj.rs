@@ -0,0 +1,422 @@
+use crate::{
+    vec3::Vec3, 
+    raytracing::{
+        Material, 
+        material, 
+        hittable::TriangleMesh
+    }, 
+    model_formats::ModelError
+};
+use std::{
+    collections::HashMap, 
+    fs, 
+    path::Path, 
+    sync::Arc
+};
+
+
+/*
+    A vertex of a face, made of an index in the list of positions and 
+    optionally in the lists of texture coordinates and normals
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FaceVertex {
+    position: usize, 
+    uv: Option<usize>, 
+    normal: Option<usize>
+}
+
+/*
+    The parameters of a material in a MTL file that we know how to map 
+    onto our materials, with the default values from the MTL specification
+*/
+#[derive(Debug, Clone)]
+struct MtlMaterial {
+    diffuse: Vec3, 
+    specular: Vec3, 
+    specular_exponent: f64, 
+    refractive_index: Option<f64>, 
+    dissolve: f64, 
+    illumination_model: u32
+}
+
+impl Default for MtlMaterial {
+    fn default() -> MtlMaterial {
+        MtlMaterial {
+            diffuse: Vec3::from_float(0.8), 
+            specular: Vec3::ZERO, 
+            specular_exponent: 0., 
+            refractive_index: None, 
+            dissolve: 1., 
+            illumination_model: 2
+        }
+    }
+}
+
+impl MtlMaterial {
+    /*
+        Transparent materials become glass, materials that are more specular 
+        than diffuse become metals with a fuzziness that grows as the 
+        specular highlight gets wider, everything else is diffuse
+    */
+    fn to_material(&self) -> Arc<Box<dyn Material + Send + Sync>> {
+        let max = |v: &Vec3| v.x.max(v.y).max(v.z);
+        let transparent = self.dissolve < 1. || [4, 6, 7, 9].contains(&self.illumination_model);
+
+        if transparent {
+            Arc::new(Box::new(material::Dielectric::new(self.refractive_index.unwrap_or(1.5))))
+        } else if max(&self.specular) > max(&self.diffuse) {
+            let fuzziness = (2. / (self.specular_exponent + 2.)).sqrt();
+            Arc::new(Box::new(material::Metal::new(self.specular, fuzziness)))
+        } else {
+            Arc::new(Box::new(material::Lambertian::new(self.diffuse)))
+        }
+    }
+}
+
+fn parse_error(line: usize, message: String) -> ModelError {
+    ModelError::Parse {line, message}
+}
+
+fn parse_floats<'a>(line: usize, statement: &str, values: impl Iterator<Item = &'a str>, count: usize) -> Result<Vec<f64>, ModelError> {
+    let values = values
+        .take(count)
+        .map(|v| v.parse::<f64>().map_err(|_| parse_error(line, format!("'{}' in {} is not a number", v, statement))))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    if values.len() < count {
+        return Err(parse_error(line, format!("not enough numbers for {}", statement)));
+    }
+    Ok(values)
+}
+
+fn parse_vec3<'a>(line: usize, statement: &str, values: impl Iterator<Item = &'a str>) -> Result<Vec3, ModelError> {
+    let v = parse_floats(line, statement, values, 3)?;
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+/*
+    OBJ indices start from 1, negative ones count backwards from the last 
+    element defined so far
+*/
+fn resolve_index(line: usize, index: &str, count: usize, what: &str) -> Result<usize, ModelError> {
+    let i = index.parse::<i64>().map_err(|_| parse_error(line, format!("'{}' is not a valid {} index", index, what)))?;
+    let resolved = match i {
+        i if i > 0 => i - 1, 
+        i if i < 0 => count as i64 + i, 
+        _ => return Err(parse_error(line, format!("{} indices start from 1", what)))
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(parse_error(line, format!("{} {} does not exist, there are {} so far", what, i, count)));
+    }
+    Ok(resolved as usize)
+}
+
+fn parse_mtl(text: &str) -> Result<HashMap<String, MtlMaterial>, ModelError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+        let statement = match tokens.next() {
+            Some(s) if !s.starts_with('#') => s, 
+            _ => continue
+        };
+
+        if statement == "newmtl" {
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            current = Some((name, MtlMaterial::default()));
+            continue;
+        }
+
+        let material = match &mut current {
+            Some((_, material)) => material, 
+            None => continue
+        };
+        match statement {
+            "Kd" => material.diffuse = parse_vec3(line_number, statement, tokens)?, 
+            "Ks" => material.specular = parse_vec3(line_number, statement, tokens)?, 
+            "Ns" => material.specular_exponent = parse_floats(line_number, statement, tokens, 1)?[0], 
+            "Ni" => material.refractive_index = Some(parse_floats(line_number, statement, tokens, 1)?[0]), 
+            "d" => material.dissolve = parse_floats(line_number, statement, tokens, 1)?[0], 
+            "Tr" => material.dissolve = 1. - parse_floats(line_number, statement, tokens, 1)?[0], 
+            "illum" => material.illumination_model = parse_floats(line_number, statement, tokens, 1)?[0] as u32, 
+            // Textures and everything else we can't render
+            _ => {}
+        }
+    }
+
+    if let Some((name, material)) = current.take() {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+/*
+    Parses the text of an OBJ file. read_mtl is called with the name of 
+    every material library referenced by the file and returns its content, 
+    a library that can't be read is an error. 
+    Faces with more than three vertices are split in triangles sharing the 
+    first vertex, and faces are grouped in one mesh per material. 
+    Normals and texture coordinates are only kept for a mesh when all of 
+    its vertices have them, otherwise the mesh gets flat shading.
+*/
+pub fn parse(
+    text: &str, 
+    read_mtl: impl Fn(&str) -> Result<String, std::io::Error>, 
+    material_override: Option<Arc<Box<dyn Material + Send + Sync>>>
+) -> Result<Vec<TriangleMesh>, ModelError> {
+    let mut positions = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+
+    let mut mtl_materials = HashMap::new();
+    // Faces grouped by material name, in the order the materials first appear
+    let mut groups: Vec<(Option<String>, Vec<[FaceVertex; 3]>)> = vec![(None, vec![])];
+    let mut current_group = 0;
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+        let statement = match tokens.next() {
+            Some(s) if !s.starts_with('#') => s, 
+            _ => continue
+        };
+
+        match statement {
+            "v" => positions.push(parse_vec3(line_number, statement, tokens)?), 
+            "vn" => normals.push(parse_vec3(line_number, statement, tokens)?), 
+            "vt" => {
+                // The v coordinate is optional and defaults to 0
+                let values = tokens.collect::<Vec<_>>();
+                let uv = parse_floats(line_number, statement, values.iter().copied(), values.len().clamp(1, 2))?;
+                uvs.push((uv[0], uv.get(1).copied().unwrap_or(0.)));
+            }, 
+            "f" => {
+                let vertices = tokens.map(|vertex| {
+                    let mut parts = vertex.split('/');
+                    let position = resolve_index(line_number, parts.next().unwrap_or(""), positions.len(), "vertex")?;
+                    let uv = match parts.next() {
+                        Some(uv) if !uv.is_empty() => Some(resolve_index(line_number, uv, uvs.len(), "texture coordinate")?), 
+                        _ => None
+                    };
+                    let normal = match parts.next() {
+                        Some(n) if !n.is_empty() => Some(resolve_index(line_number, n, normals.len(), "normal")?), 
+                        _ => None
+                    };
+                    Ok(FaceVertex {position, uv, normal})
+                }).collect::<Result<Vec<_>, ModelError>>()?;
+
+                if vertices.len() < 3 {
+                    return Err(parse_error(line_number, "a face needs at least 3 vertices".to_string()));
+                }
+                for j in 1..vertices.len() - 1 {
+                    groups[current_group].1.push([vertices[0], vertices[j], vertices[j + 1]]);
+                }
+            }, 
+            "usemtl" => {
+                let name = Some(tokens.collect::<Vec<_>>().join(" "));
+                current_group = match groups.iter().position(|(group_name, _)| *group_name == name) {
+                    Some(index) => index, 
+                    None => {
+                        groups.push((name, vec![]));
+                        groups.len() - 1
+                    }
+                };
+            }, 
+            "mtllib" if material_override.is_none() => {
+                for library in tokens {
+                    let mtl = read_mtl(library).map_err(|e| ModelError::Invalid(
+                        format!("could not read material library {}: {}", library, e)
+                    ))?;
+                    mtl_materials.extend(parse_mtl(&mtl)?);
+                }
+            }, 
+            // Objects, groups, smoothing groups and everything else we don't need
+            _ => {}
+        }
+    }
+
+    let default_material = material_override.unwrap_or_else(|| MtlMaterial::default().to_material());
+    let mut materials: HashMap<String, Arc<Box<dyn Material + Send + Sync>>> = HashMap::new();
+
+    let mut meshes = vec![];
+    for (name, faces) in groups.into_iter().filter(|(_, faces)| !faces.is_empty()) {
+        let material = match name {
+            Some(name) if mtl_materials.contains_key(&name) => materials
+                .entry(name.clone())
+                .or_insert_with(|| mtl_materials[&name].to_material())
+                .clone(), 
+            _ => default_material.clone()
+        };
+
+        let has_normals = faces.iter().flatten().all(|v| v.normal.is_some());
+        let has_uvs = faces.iter().flatten().all(|v| v.uv.is_some());
+
+        // Every distinct combination of position, uv and normal becomes a 
+        // vertex of the mesh
+        let mut vertex_indices = HashMap::new();
+        let mut mesh_positions = vec![];
+        let mut mesh_normals = vec![];
+        let mut mesh_uvs = vec![];
+        let triangles = faces.iter().map(|face| {
+            let mut triangle = [0; 3];
+            for (k, vertex) in face.iter().enumerate() {
+                let key = FaceVertex {
+                    position: vertex.position, 
+                    uv: vertex.uv.filter(|_| has_uvs), 
+                    normal: vertex.normal.filter(|_| has_normals)
+                };
+                triangle[k] = *vertex_indices.entry(key).or_insert_with(|| {
+                    mesh_positions.push(positions[key.position]);
+                    if let Some(n) = key.normal { mesh_normals.push(normals[n]); }
+                    if let Some(uv) = key.uv { mesh_uvs.push(uvs[uv]); }
+                    mesh_positions.len() - 1
+                });
+            }
+            triangle
+        }).collect();
+
+        let mut mesh = TriangleMesh::new(mesh_positions, triangles, material);
+        if has_normals {
+            mesh = mesh.with_normals(mesh_normals);
+        }
+        if has_uvs {
+            mesh = mesh.with_uvs(mesh_uvs);
+        }
+        meshes.push(mesh);
+    }
+
+    Ok(meshes)
+}
+
+/*
+    Loads an OBJ file, looking for its material libraries in the same folder
+*/
+pub fn load(path: &Path, material_override: Option<Arc<Box<dyn Material + Send + Sync>>>) -> Result<Vec<TriangleMesh>, ModelError> {
+    let text = fs::read_to_string(path)?;
+    let folder = path.parent().unwrap_or_else(|| Path::new(""));
+
+    parse(&text, |library| fs::read_to_string(folder.join(library)), material_override)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_mtl(_: &str) -> Result<String, std::io::Error> {
+        Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no material libraries in tests"))
+    }
+
+    #[test]
+    fn triangulates_polygons() {
+        let obj = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            v 0 2 0
+            f 1 2 3 4 5
+        ";
+        let meshes = parse(obj, no_mtl, None).unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].indices(), &[[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        assert!(meshes[0].normals().is_none());
+    }
+
+    #[test]
+    fn resolves_negative_indices() {
+        let obj = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            vn 0 0 1
+            f -3//-1 -2//-1 -1//-1
+        ";
+        let meshes = parse(obj, no_mtl, None).unwrap();
+        assert_eq!(meshes[0].positions()[2], Vec3::new(1., 1., 0.));
+        assert_eq!(meshes[0].normals().unwrap(), &[Vec3::new(0., 0., 1.); 3]);
+    }
+
+    #[test]
+    fn drops_normals_when_some_are_missing() {
+        let obj = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vn 0 0 1
+            f 1/1/1 2/1/1 3/1/1
+            f 1/1 3/1 4/1
+        ";
+        let meshes = parse(obj, no_mtl, None).unwrap();
+        assert!(meshes[0].normals().is_none());
+        assert!(meshes[0].uvs().is_some());
+        // Vertices 1 and 3 are shared by the two triangles
+        assert_eq!(meshes[0].positions().len(), 4);
+    }
+
+    #[test]
+    fn groups_faces_by_material() {
+        let obj = "
+            mtllib scene.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            usemtl red
+            f 1 2 3
+            usemtl glass
+            f 1 2 3
+            usemtl red
+            f 3 2 1
+        ";
+        let mtl = "
+            newmtl red
+            Kd 0.8 0.1 0.1
+            newmtl glass
+            Ni 1.33
+            d 0.5
+        ";
+        let meshes = parse(obj, |_| Ok(mtl.to_string()), None).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].triangle_count(), 2);
+
+        let red = meshes[0].material().as_ref().as_ref().as_any().downcast_ref::<material::Lambertian>().unwrap();
+        assert_eq!(*red.albedo(), Vec3::new(0.8, 0.1, 0.1));
+        let glass = meshes[1].material().as_ref().as_ref().as_any().downcast_ref::<material::Dielectric>().unwrap();
+        assert_eq!(glass.refractive_index(), 1.33);
+    }
+
+    #[test]
+    fn missing_material_libraries_are_errors() {
+        let obj = "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3";
+        let error = parse(obj, no_mtl, None).err().unwrap();
+        assert!(error.to_string().contains("missing.mtl"));
+        // Unless the materials of the file are not used
+        assert_eq!(parse(obj, no_mtl, Some(MtlMaterial::default().to_material())).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn specular_materials_become_metal() {
+        let materials = parse_mtl("newmtl steel\nKd 0.1 0.1 0.1\nKs 0.9 0.9 0.9\nNs 200").unwrap();
+        let steel = materials["steel"].to_material();
+        let metal = steel.as_ref().as_ref().as_any().downcast_ref::<material::Metal>().unwrap();
+        assert_eq!(*metal.albedo(), Vec3::from_float(0.9));
+        assert!(metal.fuzziness() < 0.1);
+    }
+
+    #[test]
+    fn reports_bad_lines() {
+        let error = parse("v 0 0 0\nv 1 0 0\nf 1 2 7", no_mtl, None).err().unwrap();
+        assert_eq!(error.to_string(), "line 3: vertex 7 does not exist, there are 2 so far");
+
+        let error = parse("v 0 zero 0", no_mtl, None).err().unwrap();
+        assert_eq!(error.to_string(), "line 1: 'zero' in v is not a number");
+    }
+}
@@ -0,0 +1,449 @@
+use crate::{
+    vec3::Vec3, 
+    raytracing::{
+        Material, 
+        material, 
+        hittable::TriangleMesh
+    }, 
+    model_formats::ModelError
+};
+use std::{
+    fs, 
+    path::Path, 
+    str::SplitAsciiWhitespace, 
+    sync::Arc
+};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii, 
+    BinaryLittleEndian, 
+    BinaryBigEndian
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Int8, 
+    UInt8, 
+    Int16, 
+    UInt16, 
+    Int32, 
+    UInt32, 
+    Float32, 
+    Float64
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<ScalarType> {
+        match name {
+            "char" | "int8" => Some(ScalarType::Int8), 
+            "uchar" | "uint8" => Some(ScalarType::UInt8), 
+            "short" | "int16" => Some(ScalarType::Int16), 
+            "ushort" | "uint16" => Some(ScalarType::UInt16), 
+            "int" | "int32" => Some(ScalarType::Int32), 
+            "uint" | "uint32" => Some(ScalarType::UInt32), 
+            "float" | "float32" => Some(ScalarType::Float32), 
+            "double" | "float64" => Some(ScalarType::Float64), 
+            _ => None
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1, 
+            ScalarType::Int16 | ScalarType::UInt16 => 2, 
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4, 
+            ScalarType::Float64 => 8
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Property {
+    Scalar { name: String, scalar_type: ScalarType }, 
+    List { name: String, count_type: ScalarType, item_type: ScalarType }
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar { name, .. } | Property::List { name, .. } => name
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String, 
+    count: usize, 
+    properties: Vec<Property>
+}
+
+struct Header {
+    format: Format, 
+    elements: Vec<Element>, 
+    // Where the body of the file starts
+    body_offset: usize
+}
+
+fn invalid(message: String) -> ModelError {
+    ModelError::Invalid(message)
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, ModelError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut offset = 0;
+    let mut line_number = 0;
+
+    loop {
+        let end = data[offset..].iter().position(|b| *b == b'\n')
+            .ok_or_else(|| invalid("the header has no end_header line".to_string()))?;
+        let line = std::str::from_utf8(&data[offset..offset + end])
+            .map_err(|_| ModelError::Parse {line: line_number + 1, message: "the header is not valid text".to_string()})?;
+        offset += end + 1;
+        line_number += 1;
+
+        let parse_error = |message: &str| ModelError::Parse {line: line_number, message: message.to_string()};
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+
+        if line_number == 1 {
+            if tokens != ["ply"] {
+                return Err(parse_error("not a PLY file, it does not start with 'ply'"));
+            }
+            continue;
+        }
+
+        match tokens.as_slice() {
+            ["format", name, _version] => format = Some(match *name {
+                "ascii" => Format::Ascii, 
+                "binary_little_endian" => Format::BinaryLittleEndian, 
+                "binary_big_endian" => Format::BinaryBigEndian, 
+                _ => return Err(parse_error(&format!("unknown format '{}'", name)))
+            }), 
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(), 
+                count: count.parse().map_err(|_| parse_error(&format!("'{}' is not a valid element count", count)))?, 
+                properties: vec![]
+            }), 
+            ["property", "list", count_type, item_type, name] => {
+                let scalar_type = |t: &str| ScalarType::parse(t).ok_or_else(|| parse_error(&format!("unknown type '{}'", t)));
+                let property = Property::List {
+                    name: name.to_string(), 
+                    count_type: scalar_type(count_type)?, 
+                    item_type: scalar_type(item_type)?
+                };
+                elements.last_mut().ok_or_else(|| parse_error("property before any element"))?.properties.push(property);
+            }, 
+            ["property", scalar_type, name] => {
+                let property = Property::Scalar {
+                    name: name.to_string(), 
+                    scalar_type: ScalarType::parse(scalar_type).ok_or_else(|| parse_error(&format!("unknown type '{}'", scalar_type)))?
+                };
+                elements.last_mut().ok_or_else(|| parse_error("property before any element"))?.properties.push(property);
+            }, 
+            ["end_header"] => break, 
+            ["comment", ..] | ["obj_info", ..] | [] => {}, 
+            _ => return Err(parse_error(&format!("unexpected header line '{}'", line)))
+        }
+    }
+
+    Ok(Header {
+        format: format.ok_or_else(|| invalid("the header has no format line".to_string()))?, 
+        elements, 
+        body_offset: offset
+    })
+}
+
+/*
+    Reads the values of the elements one after the other, every value is 
+    turned into a f64 whatever its type in the file
+*/
+trait ValueReader {
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, ModelError>;
+}
+
+struct AsciiReader<'a> {
+    tokens: SplitAsciiWhitespace<'a>
+}
+
+impl<'a> ValueReader for AsciiReader<'a> {
+    fn read(&mut self, _scalar_type: ScalarType) -> Result<f64, ModelError> {
+        let token = self.tokens.next().ok_or_else(|| invalid("the file ends before all the elements".to_string()))?;
+        token.parse().map_err(|_| invalid(format!("'{}' is not a number", token)))
+    }
+}
+
+struct BinaryReader<'a> {
+    data: &'a [u8], 
+    position: usize, 
+    big_endian: bool
+}
+
+impl<'a> ValueReader for BinaryReader<'a> {
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, ModelError> {
+        let size = scalar_type.size();
+        let bytes = self.data.get(self.position..self.position + size)
+            .ok_or_else(|| invalid("the file ends before all the elements".to_string()))?;
+        self.position += size;
+
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.big_endian {
+            buffer[..size].reverse();
+        }
+
+        Ok(match scalar_type {
+            ScalarType::Int8 => buffer[0] as i8 as f64, 
+            ScalarType::UInt8 => buffer[0] as f64, 
+            ScalarType::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64, 
+            ScalarType::UInt16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64, 
+            ScalarType::Int32 => i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64, 
+            ScalarType::UInt32 => u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64, 
+            ScalarType::Float32 => f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64, 
+            ScalarType::Float64 => f64::from_le_bytes(buffer)
+        })
+    }
+}
+
+/*
+    Reads one element, calling on_scalar and on_list with the index of 
+    each property in the element and its values
+*/
+fn read_element(
+    reader: &mut dyn ValueReader, 
+    element: &Element, 
+    mut on_scalar: impl FnMut(usize, f64), 
+    mut on_list: impl FnMut(usize, &[f64]) -> Result<(), ModelError>
+) -> Result<(), ModelError> {
+    let mut list = vec![];
+    for (i, property) in element.properties.iter().enumerate() {
+        match property {
+            Property::Scalar { scalar_type, .. } => on_scalar(i, reader.read(*scalar_type)?), 
+            Property::List { count_type, item_type, .. } => {
+                let count = reader.read(*count_type)?;
+                if count < 0. {
+                    return Err(invalid(format!("negative list length in {}", element.name)));
+                }
+                list.clear();
+                for _ in 0..count as usize {
+                    list.push(reader.read(*item_type)?);
+                }
+                on_list(i, &list)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn find_property(element: &Element, names: &[&str]) -> Option<usize> {
+    element.properties.iter().position(|p| names.contains(&p.name()))
+}
+
+/*
+    Parses a PLY file in any of its three encodings. Vertices can have 
+    normals, texture coordinates and colors, faces can have any number of 
+    vertices and get split in triangles sharing the first vertex. 
+    Elements other than vertices and faces are skipped
+*/
+pub fn parse(data: &[u8], material_override: Option<Arc<Box<dyn Material + Send + Sync>>>) -> Result<TriangleMesh, ModelError> {
+    let header = parse_header(data)?;
+    let body = &data[header.body_offset..];
+
+    let mut ascii_reader;
+    let mut binary_reader;
+    let reader: &mut dyn ValueReader = match header.format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(body).map_err(|_| invalid("the body of an ascii file is not valid text".to_string()))?;
+            ascii_reader = AsciiReader {tokens: text.split_ascii_whitespace()};
+            &mut ascii_reader
+        }, 
+        format => {
+            binary_reader = BinaryReader {data: body, position: 0, big_endian: format == Format::BinaryBigEndian};
+            &mut binary_reader
+        }
+    };
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut colors = vec![];
+    let mut triangles = vec![];
+    let (mut has_normals, mut has_uvs, mut has_colors) = (false, false, false);
+
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let find = |names: &[&str]| find_property(element, names);
+                let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+                let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+                let uv = [find(&["u", "s", "texture_u"]), find(&["v", "t", "texture_v"])];
+                let color = [find(&["red", "r"]), find(&["green", "g"]), find(&["blue", "b"])];
+
+                if position.iter().any(Option::is_none) {
+                    return Err(invalid("vertices need x, y and z properties".to_string()));
+                }
+                has_normals = normal.iter().all(Option::is_some);
+                has_uvs = uv.iter().all(Option::is_some);
+                has_colors = color.iter().all(Option::is_some);
+
+                // Integer colors go from 0 to the maximum of their type, 
+                // floating point ones from 0 to 1
+                let color_scale = match &element.properties[color[0].unwrap_or(0)] {
+                    Property::Scalar { scalar_type: ScalarType::UInt8, .. } => 1. / 255., 
+                    Property::Scalar { scalar_type: ScalarType::UInt16, .. } => 1. / 65535., 
+                    _ => 1.
+                };
+
+                let mut values = vec![0.; element.properties.len()];
+                for _ in 0..element.count {
+                    read_element(reader, element, |i, value| values[i] = value, |_, _| Ok(()))?;
+
+                    let get = |i: Option<usize>| i.map_or(0., |i| values[i]);
+                    positions.push(Vec3::new(get(position[0]), get(position[1]), get(position[2])));
+                    if has_normals {
+                        normals.push(Vec3::new(get(normal[0]), get(normal[1]), get(normal[2])));
+                    }
+                    if has_uvs {
+                        uvs.push((get(uv[0]), get(uv[1])));
+                    }
+                    if has_colors {
+                        colors.push(color_scale * Vec3::new(get(color[0]), get(color[1]), get(color[2])));
+                    }
+                }
+            }, 
+            "face" => {
+                let indices = find_property(element, &["vertex_indices", "vertex_index"])
+                    .ok_or_else(|| invalid("faces need a vertex_indices property".to_string()))?;
+                let vertex_count = positions.len();
+
+                for face in 0..element.count {
+                    read_element(reader, element, |_, _| {}, |i, list| {
+                        if i != indices {
+                            return Ok(());
+                        }
+                        if list.len() < 3 {
+                            return Err(invalid(format!("face {} has less than 3 vertices", face)));
+                        }
+                        if let Some(index) = list.iter().find(|index| **index < 0. || **index as usize >= vertex_count) {
+                            return Err(invalid(format!("face {} uses vertex {}, there are {} vertices", face, index, vertex_count)));
+                        }
+                        for j in 1..list.len() - 1 {
+                            triangles.push([list[0] as usize, list[j] as usize, list[j + 1] as usize]);
+                        }
+                        Ok(())
+                    })?;
+                }
+            }, 
+            _ => {
+                for _ in 0..element.count {
+                    read_element(reader, element, |_, _| {}, |_, _| Ok(()))?;
+                }
+            }
+        }
+    }
+
+    // With vertex colors the material should not tint them any further
+    let default_albedo = if has_colors { Vec3::ONE } else { Vec3::from_float(0.8) };
+    let material = material_override.unwrap_or_else(|| Arc::new(Box::new(material::Lambertian::new(default_albedo))));
+
+    let mut mesh = TriangleMesh::new(positions, triangles, material);
+    if has_normals {
+        mesh = mesh.with_normals(normals);
+    }
+    if has_uvs {
+        mesh = mesh.with_uvs(uvs);
+    }
+    if has_colors {
+        mesh = mesh.with_colors(colors);
+    }
+    Ok(mesh)
+}
+
+pub fn load(path: &Path, material_override: Option<Arc<Box<dyn Material + Send + Sync>>>) -> Result<Vec<TriangleMesh>, ModelError> {
+    let data = fs::read(path)?;
+    Ok(vec![parse(&data, material_override)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    fn ascii_square() -> Vec<u8> {
+        format!(
+            "ply\nformat ascii 1.0\ncomment a square\n{}{}", 
+            HEADER, 
+            "0 0 0 255 0 0\n1 0 0 255 0 0\n1 1 0 0 0 255\n0 1 0 0 0 255\n4 0 1 2 3\n"
+        ).into_bytes()
+    }
+
+    fn binary_square(big_endian: bool) -> Vec<u8> {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut data = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+
+        let vertices = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]];
+        let colors = [[255, 0, 0], [255, 0, 0], [0, 0, 255], [0, 0, 255]];
+        for (position, color) in vertices.iter().zip(colors.iter()) {
+            for coordinate in position.iter() {
+                let value = *coordinate as f32;
+                data.extend_from_slice(&if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+            }
+            data.extend_from_slice(color);
+        }
+        data.push(4);
+        for index in 0..4i32 {
+            data.extend_from_slice(&if big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+        }
+        data
+    }
+
+    fn check_square(mesh: TriangleMesh) {
+        assert_eq!(mesh.positions()[2], Vec3::new(1., 1., 0.));
+        assert_eq!(mesh.indices(), &[[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.colors().unwrap()[0], Vec3::new(1., 0., 0.));
+        assert_eq!(mesh.colors().unwrap()[3], Vec3::new(0., 0., 1.));
+        assert!(mesh.normals().is_none());
+    }
+
+    #[test]
+    fn reads_ascii() {
+        check_square(parse(&ascii_square(), None).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_little_endian() {
+        check_square(parse(&binary_square(false), None).unwrap());
+    }
+
+    #[test]
+    fn reads_binary_big_endian() {
+        check_square(parse(&binary_square(true), None).unwrap());
+    }
+
+    #[test]
+    fn reports_errors() {
+        let truncated = binary_square(false);
+        let error = parse(&truncated[..truncated.len() - 2], None).err().unwrap();
+        assert_eq!(error.to_string(), "the file ends before all the elements");
+
+        let bad_index = String::from_utf8(ascii_square()).unwrap().replace("4 0 1 2 3", "3 0 1 9");
+        let error = parse(bad_index.as_bytes(), None).err().unwrap();
+        assert_eq!(error.to_string(), "face 0 uses vertex 9, there are 4 vertices");
+
+        let error = parse(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty quad x\n", None).err().unwrap();
+        assert_eq!(error.to_string(), "line 4: unknown type 'quad'");
+    }
+}
@@ -0,0 +1,132 @@
+use crate::image_formats::Image;
+
+
+/*
+    Every pixel takes at least 'min_samples' samples, then goes on only 
+    while its estimated error is above 'threshold', up to the samples per 
+    pixel of the render. The error is how far the pixel could still be from 
+    its final value as shown in the image, where 1 is the whole range from 
+    black to white
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32, 
+    pub threshold: f64
+}
+
+impl AdaptiveSampling {
+    pub fn converged(&self, stats: &PixelStats) -> bool {
+        stats.count() >= self.min_samples.max(2) && stats.error() <= self.threshold
+    }
+}
+
+/*
+    The running mean and variance of the brightness of the samples of a 
+    pixel, updated one sample at a time with Welford's algorithm
+*/
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PixelStats {
+    count: u32, 
+    mean: f64, 
+    // The sum of the squared distances from the mean
+    m2: f64
+}
+
+impl PixelStats {
+    pub fn add(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    // The count, mean and sum of squared distances, to save them and load them back
+    pub fn parts(&self) -> (u32, f64, f64) { (self.count, self.mean, self.m2) }
+    pub fn from_parts(count: u32, mean: f64, m2: f64) -> PixelStats {
+        PixelStats {count, mean, m2}
+    }
+
+    pub fn count(&self) -> u32 { self.count }
+
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 { 0. } else { self.m2 / (self.count - 1) as f64 }
+    }
+
+    /*
+        The standard error of the mean brightness, after the sRGB curve of 
+        the image. The curve is close to a square root: around brightness 
+        L a change of d shows up as d / (2 sqrt(L))
+    */
+    pub fn error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let standard_error = (self.variance() / self.count as f64).sqrt();
+        if standard_error == 0. {
+            return 0.;
+        }
+        standard_error / (2. * self.mean.max(1e-6).sqrt())
+    }
+}
+
+/*
+    Black to red to yellow to white as 't' goes from 0 to 1
+*/
+fn heat_color(t: f64) -> (u8, u8, u8) {
+    let t = t.clamp(0., 1.) * 3.;
+    let channel = |c: f64| (c.clamp(0., 1.) * 255.).round() as u8;
+    (channel(t), channel(t - 1.), channel(t - 2.))
+}
+
+/*
+    Draws how many samples every pixel took, 'counts' goes row by row from 
+    the top. The pixels that took the least samples are black and the ones 
+    that took the most are white
+*/
+pub fn draw_heatmap(counts: &[u32], image: &mut impl Image) {
+    let min = counts.iter().copied().min().unwrap_or(0);
+    let max = counts.iter().copied().max().unwrap_or(0);
+    let range = (max - min).max(1) as f64;
+
+    let width = image.width();
+    for (i, count) in counts.iter().enumerate() {
+        image.putpixel(i % width, i / width, heat_color((count - min) as f64 / range));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_variance() {
+        let mut stats = PixelStats::default();
+        for x in [2., 4., 4., 4., 5., 5., 7., 9.].iter() {
+            stats.add(*x);
+        }
+        assert_eq!(stats.count(), 8);
+        assert!((stats.mean - 5.).abs() < 1e-12);
+        assert!((stats.variance() - 32. / 7.).abs() < 1e-12);
+    }
+
+    #[test]
+    fn flat_pixels_converge_at_once() {
+        let adaptive = AdaptiveSampling {min_samples: 4, threshold: 0.01};
+        let mut flat = PixelStats::default();
+        let mut noisy = PixelStats::default();
+        for i in 0..4 {
+            flat.add(0.5);
+            noisy.add(if i % 2 == 0 { 0. } else { 1. });
+            assert!(!adaptive.converged(&flat) || i == 3);
+        }
+        assert!(adaptive.converged(&flat));
+        assert!(!adaptive.converged(&noisy));
+    }
+
+    #[test]
+    fn heat_goes_from_black_to_white() {
+        assert_eq!(heat_color(0.), (0, 0, 0));
+        assert_eq!(heat_color(0.5), (255, 128, 0));
+        assert_eq!(heat_color(1.), (255, 255, 255));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::vec3::Vec3;
+use super::distribution::Distribution2D;
+use std::f64::consts::PI;
+
+
+/*
+    What a ray sees when it leaves the scene without hitting anything, 
+    this is also the light that comes from far away like the sky
+*/
+pub trait Background {
+    fn color(&self, direction: &Vec3) -> Vec3;
+
+    /*
+        Picks a direction towards the background with a probability that 
+        follows how bright it is, given two uniform random numbers. Returns 
+        the direction, the color seen there and the probability density of 
+        picking it over the sphere of directions. 
+        Backgrounds that return None are only found by rays bouncing around
+    */
+    fn sample(&self, _u: (f64, f64)) -> Option<(Vec3, Vec3, f64)> {
+        None
+    }
+
+    /*
+        The probability density of sample picking 'direction'
+    */
+    fn pdf(&self, _direction: &Vec3) -> f64 {
+        0.
+    }
+}
+
+/*
+    The same color in every direction, black for studio renders where
+    all the light comes from the objects in the scene
+*/
+pub struct SolidColor {
+    color: Vec3
+}
+
+impl SolidColor {
+    pub fn new(color: Vec3) -> SolidColor {
+        SolidColor {color}
+    }
+}
+
+impl Background for SolidColor {
+    fn color(&self, _direction: &Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+/*
+    Blends between 'bottom' for rays going straight down and 'top' for
+    rays going straight up
+*/
+pub struct Gradient {
+    bottom: Vec3, 
+    top: Vec3
+}
+
+impl Gradient {
+    pub fn new(bottom: Vec3, top: Vec3) -> Gradient {
+        Gradient {bottom, top}
+    }
+}
+
+impl Background for Gradient {
+    fn color(&self, direction: &Vec3) -> Vec3 {
+        let unit_direction = direction.unit_vector();
+        let t = 0.5 * (1.0 + unit_direction.y);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
+
+/*
+    An image wrapped around the scene in latitude-longitude layout: the 
+    columns go around the vertical axis starting from -z, the first row 
+    is straight up and the last one straight down. 
+    Bright texels, like the sun, are sampled more often than dark ones
+*/
+pub struct EnvironmentMap {
+    width: usize, 
+    height: usize, 
+    texels: Vec<Vec3>, 
+    // Radians around the vertical axis, counter-clockwise seen from above
+    rotation: f64, 
+    intensity: f64, 
+    distribution: Option<Distribution2D>
+}
+
+fn rotate_y(v: &Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    Vec3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z)
+}
+
+impl EnvironmentMap {
+    /*
+        The texels are linear colors stored row by row starting from the top
+    */
+    pub fn new(width: usize, height: usize, texels: Vec<Vec3>) -> EnvironmentMap {
+        assert!(width > 0 && height > 0, "the environment map is empty");
+        assert_eq!(texels.len(), width * height, "the environment map must have width * height texels");
+
+        /*
+            The rows near the poles cover a smaller part of the sphere than 
+            the ones at the horizon, so they are made less likely
+        */
+        let weights: Vec<f64> = texels.iter().enumerate().map(|(i, texel)| {
+            let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+            texel.luminance().max(0.) * theta.sin()
+        }).collect();
+        let distribution = Some(Distribution2D::new(width, height, &weights))
+            .filter(|distribution| distribution.integral() > 0.);
+
+        EnvironmentMap {width, height, texels, rotation: 0., intensity: 1., distribution}
+    }
+
+    pub fn with_rotation(self, degrees: f64) -> EnvironmentMap {
+        EnvironmentMap {rotation: degrees.to_radians(), ..self}
+    }
+
+    pub fn with_intensity(self, intensity: f64) -> EnvironmentMap {
+        EnvironmentMap {intensity, ..self}
+    }
+
+    /*
+        Maps a direction to the texture coordinates of the image, both 
+        going from 0 to 1
+    */
+    pub fn direction_to_uv(direction: &Vec3) -> (f64, f64) {
+        let d = direction.unit_vector();
+        let u = 0.5 + d.x.atan2(-d.z) / (2. * PI);
+        let v = d.y.clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    pub fn uv_to_direction((u, v): (f64, f64)) -> Vec3 {
+        let (sin_phi, cos_phi) = ((u - 0.5) * 2. * PI).sin_cos();
+        let (sin_theta, cos_theta) = (v * PI).sin_cos();
+        Vec3::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi)
+    }
+
+    fn texel(&self, (u, v): (f64, f64)) -> Vec3 {
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.intensity * self.texels[y * self.width + x]
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn color(&self, direction: &Vec3) -> Vec3 {
+        let uv = EnvironmentMap::direction_to_uv(&rotate_y(direction, -self.rotation));
+        self.texel(uv)
+    }
+
+    fn sample(&self, u: (f64, f64)) -> Option<(Vec3, Vec3, f64)> {
+        let (uv, uv_pdf) = self.distribution.as_ref()?.sample_continuous(u);
+
+        /*
+            The image is stretched over the sphere, a texel at latitude theta 
+            covers 2 pi^2 sin(theta) times its area in the image
+        */
+        let sin_theta = (uv.1 * PI).sin();
+        if uv_pdf == 0. || sin_theta <= 0. {
+            return None;
+        }
+        let pdf = uv_pdf / (2. * PI * PI * sin_theta);
+
+        let direction = rotate_y(&EnvironmentMap::uv_to_direction(uv), self.rotation);
+        Some((direction, self.texel(uv), pdf))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let distribution = match &self.distribution {
+            Some(distribution) => distribution, 
+            None => return 0.
+        };
+
+        let uv = EnvironmentMap::direction_to_uv(&rotate_y(direction, -self.rotation));
+        let sin_theta = (uv.1 * PI).sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        distribution.pdf(uv) / (2. * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_goes_from_bottom_to_top() {
+        let (bottom, top) = (Vec3::ONE, Vec3::new(0.5, 0.7, 1.0));
+        let sky = Gradient::new(bottom, top);
+        assert_eq!(sky.color(&Vec3::new(0., -2., 0.)), bottom);
+        assert_eq!(sky.color(&Vec3::new(0., 3., 0.)), top);
+        assert_eq!(sky.color(&Vec3::new(1., 0., 0.)), 0.5 * (bottom + top));
+    }
+
+    #[test]
+    fn environment_map_layout() {
+        // Each texel of a 4x2 map has a different color
+        let texels = (0..8).map(|i| Vec3::from_float(i as f64)).collect();
+        let map = EnvironmentMap::new(4, 2, texels);
+
+        let color = |x, y, z| map.color(&Vec3::new(x, y, z)).x;
+        // -z is the middle of the image, +x is on its right
+        assert_eq!(color(-0.1, 0.5, -1.), 1.);
+        assert_eq!(color(0.1, 0.5, -1.), 2.);
+        assert_eq!(color(1., 0.5, 0.1), 3.);
+        assert_eq!(color(-1., 0.5, 0.1), 0.);
+        // The bottom row is below the horizon
+        assert_eq!(color(0.1, -0.5, -1.), 6.);
+        assert_eq!(color(0., -1., -0.01), 6.);
+
+        let rotated = EnvironmentMap::new(4, 2, (0..8).map(|i| Vec3::from_float(i as f64)).collect())
+            .with_rotation(90.)
+            .with_intensity(2.);
+        // Turned a quarter to the left, what was in front is now on the left
+        assert_eq!(rotated.color(&Vec3::new(-1., 0.5, 0.1)).x, 2.);
+    }
+
+    #[test]
+    fn environment_sampling() {
+        // A dark map with a single bright texel
+        let mut texels = vec![Vec3::from_float(0.01); 16 * 8];
+        texels[2 * 16 + 5] = Vec3::from_float(1000.);
+        let map = EnvironmentMap::new(16, 8, texels).with_rotation(30.);
+
+        let mut bright = 0;
+        let n = 1000;
+        let mut estimate = 0.;
+        for i in 0..n {
+            let u = ((i as f64 + 0.5) / n as f64, ((i as f64 + 0.5) * 0.618034) % 1.);
+            let (direction, color, pdf) = map.sample(u).unwrap();
+            assert_eq!(color, map.color(&direction));
+            assert!((map.pdf(&direction) / pdf - 1.).abs() < 1e-6);
+            if color.x > 1. {
+                bright += 1;
+            }
+            estimate += color.x / pdf / n as f64;
+        }
+        assert!(bright > 950);
+
+        // The total light coming from the map, integrated over the sphere
+        let mut expected = 0.;
+        for (i, texel) in map.texels.iter().enumerate() {
+            let (theta0, theta1) = ((i / 16) as f64 * PI / 8., (i / 16 + 1) as f64 * PI / 8.);
+            expected += texel.x * (theta0.cos() - theta1.cos()) * 2. * PI / 16.;
+        }
+        assert!((estimate - expected).abs() < 0.05 * expected);
+    }
+}
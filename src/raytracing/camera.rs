@@ -32,6 +32,7 @@ impl Camera {
         let u = Vec3::cross(vup, &w).unit_vector();
         let v = Vec3::cross(&w, &u);
 
+        #[allow(clippy::clone_on_copy)]
         let origin = lookfrom.clone();
         let horizontal = focus_dist * viewport_width * u;
         let vertical = focus_dist * viewport_height * v;
@@ -47,14 +48,16 @@ impl Camera {
         }
     }
 
-    fn random_in_unit_disk() -> (f64, f64) {
-        let rand_double = || -1.0 + 2.0 * rand::random::<f64>();
-
-        (rand_double(), rand_double())
+    fn random_in_unit_disk(u: (f64, f64)) -> (f64, f64) {
+        (-1.0 + 2.0 * u.0, -1.0 + 2.0 * u.1)
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let (rd_x, rd_y) = Camera::random_in_unit_disk();
+    /*
+        The ray through the point ('s', 't') of the image, leaving from the 
+        point of the lens picked by 'u_lens'
+    */
+    pub fn get_ray(&self, s: f64, t: f64, u_lens: (f64, f64)) -> Ray {
+        let (rd_x, rd_y) = Camera::random_in_unit_disk(u_lens);
         let (rd_x, rd_y) = (self.lens_radius * rd_x, self.lens_radius * rd_y);
         let offset = self.u * rd_x + self.v * rd_y;
 
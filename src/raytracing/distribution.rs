@@ -0,0 +1,167 @@
+/*
+    A piecewise-constant probability density over [0, 1), made of as many
+    equally wide pieces as there are weights, each as likely to be picked
+    as its weight
+*/
+pub struct Distribution1D {
+    weights: Vec<f64>, 
+    // cdf[i] is the probability of landing before piece i, cdf[n] is 1
+    cdf: Vec<f64>, 
+    total: f64
+}
+
+impl Distribution1D {
+    pub fn new(weights: Vec<f64>) -> Distribution1D {
+        assert!(!weights.is_empty(), "a distribution needs at least one weight");
+        let n = weights.len();
+
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.);
+        for w in &weights {
+            assert!(*w >= 0. && w.is_finite(), "weights must be finite and not negative");
+            cdf.push(cdf.last().unwrap() + w / n as f64);
+        }
+
+        let total = cdf[n];
+        if total > 0. {
+            cdf.iter_mut().for_each(|c| *c /= total);
+        } else {
+            // Nothing to prefer, every piece is as likely
+            cdf.iter_mut().enumerate().for_each(|(i, c)| *c = i as f64 / n as f64);
+        }
+
+        Distribution1D {weights, cdf, total}
+    }
+
+    pub fn len(&self) -> usize { self.weights.len() }
+
+    /*
+        The average of the weights, zero when they are all zero
+    */
+    pub fn integral(&self) -> f64 { self.total }
+
+    /*
+        Turns a uniform random number into a point of [0, 1) following the
+        distribution, also returning its density and the piece it is in
+    */
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // The last piece whose cdf starts at or before u, skipping empty ones
+        let index = self.cdf.partition_point(|c| *c <= u).clamp(1, self.len()) - 1;
+
+        let start = self.cdf[index];
+        let width = self.cdf[index + 1] - start;
+        let offset = if width > 0. { (u - start) / width } else { 0. };
+
+        let x = ((index as f64 + offset) / self.len() as f64).min(1. - f64::EPSILON);
+        (x, self.pdf_of(index), index)
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        let index = ((x * self.len() as f64) as usize).min(self.len() - 1);
+        self.pdf_of(index)
+    }
+
+    /*
+        The density of the distribution anywhere inside piece 'index'
+    */
+    pub fn pdf_of(&self, index: usize) -> f64 {
+        if self.total > 0. {
+            self.weights[index] / self.total
+        } else {
+            1.
+        }
+    }
+}
+
+/*
+    A piecewise-constant density over [0, 1)^2 given by a grid of weights
+    stored row by row. Rows are picked first by their total weight, then a
+    position inside the chosen row
+*/
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>, 
+    marginal: Distribution1D
+}
+
+impl Distribution2D {
+    pub fn new(width: usize, height: usize, weights: &[f64]) -> Distribution2D {
+        assert_eq!(weights.len(), width * height, "the distribution must have width * height weights");
+
+        let rows: Vec<_> = weights.chunks_exact(width).map(|row| Distribution1D::new(row.to_vec())).collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+
+        Distribution2D {rows, marginal}
+    }
+
+    pub fn integral(&self) -> f64 { self.marginal.integral() }
+
+    /*
+        Returns a point (x, y) of [0, 1)^2 and its density
+    */
+    pub fn sample_continuous(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u.1);
+        let (x, pdf_x, _) = self.rows[row].sample_continuous(u.0);
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, (x, y): (f64, f64)) -> f64 {
+        let row = ((y * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        self.marginal.pdf_of(row) * self.rows[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_follow_the_weights() {
+        let distribution = Distribution1D::new(vec![1., 0., 3.]);
+        assert_eq!(distribution.integral(), 4. / 3.);
+
+        let (x, pdf, index) = distribution.sample_continuous(0.1);
+        assert_eq!(index, 0);
+        assert!((x - 0.4 / 3.).abs() < 1e-12);
+        assert_eq!(pdf, 0.75);
+
+        // The empty piece in the middle is never picked
+        let (x, pdf, index) = distribution.sample_continuous(0.25);
+        assert_eq!(index, 2);
+        assert!((x - 2. / 3.).abs() < 1e-12);
+        assert_eq!(pdf, 2.25);
+        assert!(distribution.sample_continuous(0.9999999).0 < 1.);
+    }
+
+    #[test]
+    fn zero_weights_are_uniform() {
+        let distribution = Distribution1D::new(vec![0., 0.]);
+        assert_eq!(distribution.sample_continuous(0.75), (0.75, 1., 1));
+    }
+
+    #[test]
+    fn density_follows_the_weights() {
+        let weights: Vec<f64> = (0..12).map(|i| (i * 7 % 5) as f64).collect();
+        let distribution = Distribution2D::new(4, 3, &weights);
+        let average = weights.iter().sum::<f64>() / 12.;
+        assert!((distribution.integral() - average).abs() < 1e-12);
+
+        let n = 64;
+        let mut hits = [0; 12];
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let ((x, y), pdf) = distribution.sample_continuous(u);
+                let cell = (y * 3.) as usize * 4 + (x * 4.) as usize;
+                assert!((pdf - weights[cell] / average).abs() < 1e-12);
+                assert_eq!(distribution.pdf((x, y)), pdf);
+                hits[cell] += 1;
+            }
+        }
+
+        // Each cell is picked about as often as its share of the weights
+        for (cell, count) in hits.iter().enumerate() {
+            let expected = weights[cell] / (12. * average) * (n * n) as f64;
+            assert!((*count as f64 - expected).abs() <= 2. * n as f64, "cell {}", cell);
+        }
+    }
+}
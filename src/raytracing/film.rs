@@ -0,0 +1,139 @@
+use crate::{
+    vec3::Vec3, 
+    image_formats::FloatImage
+};
+use super::{
+    adaptive::PixelStats, 
+    PathSample
+};
+
+
+/*
+    The samples taken so far for one pixel: their sum and how spread out 
+    they are, the sums of the normal and albedo layers and the distance of 
+    the nearest hit, infinite while no sample hit anything
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct FilmPixel {
+    pub sum: Vec3, 
+    pub stats: PixelStats, 
+    pub normal: Vec3, 
+    pub albedo: Vec3, 
+    pub depth: f64
+}
+
+impl FilmPixel {
+    pub fn add(&mut self, sample: &PathSample) {
+        self.sum += sample.radiance;
+        self.stats.add(sample.radiance.luminance());
+        self.normal += sample.normal;
+        self.albedo += sample.albedo;
+        // Misses are infinitely far, averaging them in would make the 
+        // pixels on the edges of objects infinitely far too
+        self.depth = self.depth.min(sample.depth);
+    }
+
+    // The average of the samples, black before the first one
+    pub fn color(&self) -> Vec3 {
+        self.sum / self.samples()
+    }
+
+    // The average of the normals scaled back to length 1, zero when no sample hit anything
+    pub fn normal(&self) -> Vec3 {
+        match self.normal.length() {
+            length if length > 0. => self.normal / length, 
+            _ => Vec3::ZERO
+        }
+    }
+
+    pub fn albedo(&self) -> Vec3 {
+        self.albedo / self.samples()
+    }
+
+    pub fn depth(&self) -> f64 {
+        self.depth
+    }
+
+    fn samples(&self) -> f64 {
+        self.stats.count().max(1) as f64
+    }
+}
+
+/*
+    Where the renderer adds up the light of the samples of every pixel, 
+    row by row from the top. Samples can be added over many passes, the 
+    image can be taken at any time between them
+*/
+pub struct Film {
+    width: usize, 
+    height: usize, 
+    pixels: Vec<FilmPixel>
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Film {
+        let empty = FilmPixel {
+            sum: Vec3::ZERO, 
+            stats: PixelStats::default(), 
+            normal: Vec3::ZERO, 
+            albedo: Vec3::ZERO, 
+            depth: f64::INFINITY
+        };
+        Film {width, height, pixels: vec![empty; width * height]}
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+    pub fn pixels(&self) -> &[FilmPixel] { &self.pixels }
+    pub fn pixels_mut(&mut self) -> &mut [FilmPixel] { &mut self.pixels }
+
+    pub fn sample_counts(&self) -> Vec<u32> {
+        self.pixels.iter().map(|pixel| pixel.stats.count()).collect()
+    }
+
+    // The average color of every pixel, before any clamping
+    pub fn image(&self) -> FloatImage {
+        self.layer(FilmPixel::color)
+    }
+
+    // The average direction of the surface seen by every pixel
+    pub fn normals(&self) -> FloatImage {
+        self.layer(FilmPixel::normal)
+    }
+
+    pub fn albedo(&self) -> FloatImage {
+        self.layer(FilmPixel::albedo)
+    }
+
+    pub fn depth(&self) -> Vec<f64> {
+        self.pixels.iter().map(FilmPixel::depth).collect()
+    }
+
+    fn layer(&self, value: impl Fn(&FilmPixel) -> Vec3) -> FloatImage {
+        FloatImage::new(self.width, self.height, self.pixels.iter().map(value).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(normal: Vec3, depth: f64) -> PathSample {
+        PathSample {radiance: Vec3::ONE, normal, albedo: Vec3::ONE, depth}
+    }
+
+    #[test]
+    fn edges_keep_the_nearest_hit() {
+        let mut film = Film::new(2, 1);
+        let edge = &mut film.pixels_mut()[0];
+        edge.add(&sample(Vec3::new(1., 0., 0.), 3.));
+        edge.add(&sample(Vec3::ZERO, f64::INFINITY));
+        edge.add(&sample(Vec3::new(0., 1., 0.), 2.));
+
+        assert_eq!(film.depth(), [2., f64::INFINITY]);
+        let normal = film.normals().pixels()[0];
+        assert!((normal.length() - 1.).abs() < 1e-12);
+        assert!((normal.x - normal.y).abs() < 1e-12);
+        assert_eq!(film.normals().pixels()[1], Vec3::ZERO);
+    }
+}
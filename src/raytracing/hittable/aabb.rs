@@ -0,0 +1,94 @@
+use crate::{
+    vec3::{Vec3, Axis}, 
+    raytracing::Ray
+};
+
+
+/*
+    An axis-aligned bounding box, described by its minimum and maximum 
+    corners. An "empty" box has its minimum at +infinity and its maximum 
+    at -infinity so that it can be grown by surrounding it with other boxes
+*/
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub minimum: Vec3, 
+    pub maximum: Vec3
+}
+
+impl Aabb {
+    pub const EMPTY: Aabb = Aabb {
+        minimum: Vec3 { x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY }, 
+        maximum: Vec3 { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY }
+    };
+
+    pub fn new(minimum: Vec3, maximum: Vec3) -> Aabb {
+        Aabb {minimum, maximum}
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb::new(a.minimum.min(&b.minimum), a.maximum.max(&b.maximum))
+    }
+
+    pub fn including(&self, point: &Vec3) -> Aabb {
+        Aabb::new(self.minimum.min(point), self.maximum.max(point))
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn longest_axis(&self) -> Axis {
+        let extent = self.maximum - self.minimum;
+        if extent.x >= extent.y && extent.x >= extent.z {
+            Axis::X
+        } else if extent.y >= extent.z {
+            Axis::Y
+        } else {
+            Axis::Z
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let extent = self.maximum - self.minimum;
+        if extent.x < 0. || extent.y < 0. || extent.z < 0. {
+            return 0.;
+        }
+        2. * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let inv_direction = Vec3::ONE / ray.direction();
+        self.hit_precomputed(ray.origin(), &inv_direction, t_min, t_max)
+    }
+
+    /*
+        Same as hit, but takes the reciprocal of the ray direction so that 
+        traversals testing many boxes against the same ray only compute it once
+    */
+    pub fn hit_precomputed(&self, origin: &Vec3, inv_direction: &Vec3, mut t_min: f64, mut t_max: f64) -> bool {
+        /*
+            Slab test: the ray is inside the box for the values of t where 
+            it is between the two planes of every axis at the same time. 
+            A division by a zero direction component gives an infinity with 
+            the right sign, which keeps the test correct for axis-parallel rays
+        */
+        for axis in [Axis::X, Axis::Y, Axis::Z].iter() {
+            let inv_d = inv_direction.component(*axis);
+            let origin = origin.component(*axis);
+
+            let mut t0 = (self.minimum.component(*axis) - origin) * inv_d;
+            let mut t1 = (self.maximum.component(*axis) - origin) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
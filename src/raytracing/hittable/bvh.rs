@@ -0,0 +1,121 @@
+use crate::raytracing::{
+    Ray, 
+    Hittable, 
+    hittable::{HitRecord, Aabb}
+};
+use std::cmp::Ordering;
+
+
+/*
+    A bounding volume hierarchy: a binary tree where every node stores the 
+    box surrounding all of its children. A ray that misses a node's box 
+    cannot hit anything inside it, so a whole subtree gets skipped with a 
+    single box test and traversal takes logarithmic time in the number of 
+    objects instead of the linear time of checking all of them.
+*/
+pub enum BvhNode {
+    Empty, 
+    Leaf(Box<dyn Hittable + Send + Sync>), 
+    Branch {
+        bbox: Aabb, 
+        left: Box<BvhNode>, 
+        right: Box<BvhNode>
+    }
+}
+
+impl BvhNode {
+    /*
+        Builds the tree by splitting the objects in two halves at the median 
+        of their centers, along the axis where the centers are most spread out
+    */
+    pub fn new(mut objects: Vec<Box<dyn Hittable + Send + Sync>>) -> BvhNode {
+        match objects.len() {
+            0 => BvhNode::Empty, 
+            1 => BvhNode::Leaf(objects.pop().unwrap()), 
+            n => {
+                let axis = objects
+                    .iter()
+                    .fold(Aabb::EMPTY, |acc, o| acc.including(&o.bounding_box().centroid()))
+                    .longest_axis();
+                
+                objects.select_nth_unstable_by(n / 2, |a, b| {
+                    let a = a.bounding_box().centroid().component(axis);
+                    let b = b.bounding_box().centroid().component(axis);
+                    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+                });
+
+                let right_objects = objects.split_off(n / 2);
+                let left = BvhNode::new(objects);
+                let right = BvhNode::new(right_objects);
+
+                BvhNode::Branch {
+                    bbox: Aabb::surrounding(&left.bounding_box(), &right.bounding_box()), 
+                    left: Box::new(left), 
+                    right: Box::new(right)
+                }
+            }
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        match self {
+            BvhNode::Empty => None, 
+            BvhNode::Leaf(object) => object.hit(ray, t_min, t_max), 
+            BvhNode::Branch { bbox, left, right } => {
+                if !bbox.hit(ray, t_min, t_max) {
+                    return None;
+                }
+
+                let left_hit = left.hit(ray, t_min, t_max);
+                let closest = left_hit.as_ref().map_or(t_max, |hr| hr.t);
+                right.hit(ray, t_min, closest).or(left_hit)
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self {
+            BvhNode::Empty => Aabb::EMPTY, 
+            BvhNode::Leaf(object) => object.bounding_box(), 
+            BvhNode::Branch { bbox, .. } => *bbox
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        vec3::Vec3, 
+        raytracing::hittable::{self, fixtures::{spheres, random_spheres}}
+    };
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn empty_bvh_hits_nothing() {
+        let bvh = BvhNode::new(vec![]);
+        let ray = Ray::new(Vec3::ZERO, Vec3::new(0., 0., -1.));
+        assert!(bvh.hit(&ray, 0., f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn same_hits_as_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let params = random_spheres(&mut rng, 500);
+
+        let bvh = BvhNode::new(spheres(&params));
+        let objects = spheres(&params);
+
+        for _ in 0..1000 {
+            let origin = Vec3::new(0., 0., 30.);
+            let target = Vec3::new(rng.gen_range(-10. ..10.), rng.gen_range(-10. ..10.), 0.);
+            let ray = Ray::new(origin, target - origin);
+
+            let from_bvh = bvh.hit(&ray, 0.001, f64::INFINITY).map(|hr| hr.t);
+            let from_list = hittable::hits(&objects, &ray, 0.001, f64::INFINITY).map(|hr| hr.t);
+            assert_eq!(from_bvh, from_list);
+        }
+    }
+}
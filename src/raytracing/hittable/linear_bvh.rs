@@ -0,0 +1,315 @@
+use crate::{
+    vec3::{Vec3, Axis}, 
+    raytracing::{
+        Ray, 
+        Hittable, 
+        hittable::{HitRecord, Aabb}
+    }
+};
+use std::{cmp::Ordering, fmt};
+
+
+const BUCKET_COUNT: usize = 16;
+const MAX_OBJECTS_IN_LEAF: usize = 4;
+const MAX_DEPTH: usize = 64;
+const TRAVERSAL_COST: f64 = 1.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct BuildStats {
+    pub node_count: usize, 
+    pub leaf_count: usize, 
+    pub max_depth: usize, 
+    // leaf_size_histogram[n] is how many leaves hold exactly n objects
+    pub leaf_size_histogram: Vec<usize>
+}
+
+impl fmt::Display for BuildStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{} nodes, {} leaves, depth {}, leaf sizes:", 
+            self.node_count, self.leaf_count, self.max_depth
+        )?;
+        for (size, count) in self.leaf_size_histogram.iter().enumerate().filter(|(_, c)| **c > 0) {
+            write!(f, " {}x{}", count, size)?;
+        }
+        Ok(())
+    }
+}
+
+struct LinearNode {
+    bbox: Aabb, 
+    /*
+        For leaves this is the index of the first object in the leaf, for 
+        interior nodes it's the index of the second child: the first child 
+        is always stored right after its parent
+    */
+    offset: u32, 
+    object_count: u32, 
+    axis: Axis
+}
+
+struct ObjectInfo {
+    index: usize, 
+    bbox: Aabb, 
+    centroid: Vec3
+}
+
+/*
+    A bounding volume hierarchy built with the surface area heuristic: at 
+    each node the object centers are binned in buckets along the widest axis 
+    and the split between buckets that minimizes the expected cost of 
+    tracing a ray (proportional to the surface area of the two children 
+    times the number of objects inside them) is chosen. 
+    The tree is stored as a flat array in depth-first order, so traversal 
+    follows indices instead of pointers and needs no allocation per node.
+*/
+pub struct LinearBvh {
+    nodes: Vec<LinearNode>, 
+    objects: Vec<Box<dyn Hittable + Send + Sync>>, 
+    stats: BuildStats
+}
+
+impl LinearBvh {
+    pub fn new(objects: Vec<Box<dyn Hittable + Send + Sync>>) -> LinearBvh {
+        let mut infos = objects.iter().enumerate().map(|(index, object)| {
+            let bbox = object.bounding_box();
+            ObjectInfo {index, bbox, centroid: bbox.centroid()}
+        }).collect::<Vec<_>>();
+
+        let mut nodes = Vec::with_capacity(2 * objects.len());
+        let mut order = Vec::with_capacity(objects.len());
+        let mut stats = BuildStats::default();
+        if !infos.is_empty() {
+            LinearBvh::build(&mut infos, 0, &mut nodes, &mut order, &mut stats);
+        }
+        stats.node_count = nodes.len();
+
+        let mut objects = objects.into_iter().map(Some).collect::<Vec<_>>();
+        let objects = order.iter().map(|i| objects[*i].take().unwrap()).collect();
+
+        LinearBvh {nodes, objects, stats}
+    }
+
+    pub fn stats(&self) -> &BuildStats { &self.stats }
+
+    fn build(
+        infos: &mut [ObjectInfo], 
+        depth: usize, 
+        nodes: &mut Vec<LinearNode>, 
+        order: &mut Vec<usize>, 
+        stats: &mut BuildStats
+    ) -> usize {
+        let node_index = nodes.len();
+        nodes.push(LinearNode {bbox: Aabb::EMPTY, offset: 0, object_count: 0, axis: Axis::X});
+        stats.max_depth = stats.max_depth.max(depth);
+
+        let bbox = infos.iter().fold(Aabb::EMPTY, |acc, info| Aabb::surrounding(&acc, &info.bbox));
+        let centroid_bounds = infos.iter().fold(Aabb::EMPTY, |acc, info| acc.including(&info.centroid));
+        let axis = centroid_bounds.longest_axis();
+        let axis_min = centroid_bounds.minimum.component(axis);
+        let axis_extent = centroid_bounds.maximum.component(axis) - axis_min;
+
+        let n = infos.len();
+        let split = if n == 1 || axis_extent <= 0. || depth + 1 >= MAX_DEPTH {
+            // Either there is nothing to split or all the centers are in the 
+            // same spot, in which case no split would separate them
+            None
+        } else {
+            let bucket_of = |info: &ObjectInfo| {
+                let relative = (info.centroid.component(axis) - axis_min) / axis_extent;
+                ((relative * BUCKET_COUNT as f64) as usize).min(BUCKET_COUNT - 1)
+            };
+
+            let mut buckets = [(0usize, Aabb::EMPTY); BUCKET_COUNT];
+            for info in infos.iter() {
+                let bucket = &mut buckets[bucket_of(info)];
+                bucket.0 += 1;
+                bucket.1 = Aabb::surrounding(&bucket.1, &info.bbox);
+            }
+
+            // costs[i] is the cost of putting buckets 0..=i on the left and 
+            // the remaining ones on the right
+            let mut costs = [0.; BUCKET_COUNT - 1];
+            let (mut count, mut area_box) = (0, Aabb::EMPTY);
+            for i in 0..BUCKET_COUNT - 1 {
+                count += buckets[i].0;
+                area_box = Aabb::surrounding(&area_box, &buckets[i].1);
+                costs[i] = count as f64 * area_box.surface_area();
+            }
+            let (mut count, mut area_box) = (0, Aabb::EMPTY);
+            for i in (1..BUCKET_COUNT).rev() {
+                count += buckets[i].0;
+                area_box = Aabb::surrounding(&area_box, &buckets[i].1);
+                costs[i - 1] += count as f64 * area_box.surface_area();
+            }
+
+            let (best_bucket, best_cost) = costs
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal))
+                .map(|(i, cost)| (i, TRAVERSAL_COST + cost / bbox.surface_area()))
+                .unwrap();
+            let leaf_cost = n as f64;
+
+            if n > MAX_OBJECTS_IN_LEAF || best_cost < leaf_cost {
+                let mut mid = 0;
+                for i in 0..n {
+                    if bucket_of(&infos[i]) <= best_bucket {
+                        infos.swap(i, mid);
+                        mid += 1;
+                    }
+                }
+
+                if mid == 0 || mid == n {
+                    // Can happen when the surface areas are all zero or NaN, 
+                    // fall back to splitting at the median
+                    mid = n / 2;
+                    infos.select_nth_unstable_by(mid, |a, b| {
+                        a.centroid.component(axis)
+                            .partial_cmp(&b.centroid.component(axis))
+                            .unwrap_or(Ordering::Equal)
+                    });
+                }
+                Some(mid)
+            } else {
+                None
+            }
+        };
+
+        nodes[node_index] = match split {
+            Some(mid) => {
+                let (left, right) = infos.split_at_mut(mid);
+                LinearBvh::build(left, depth + 1, nodes, order, stats);
+                let second_child = LinearBvh::build(right, depth + 1, nodes, order, stats);
+                LinearNode {bbox, offset: second_child as u32, object_count: 0, axis}
+            }, 
+            None => {
+                let first_object = order.len();
+                order.extend(infos.iter().map(|info| info.index));
+
+                stats.leaf_count += 1;
+                if stats.leaf_size_histogram.len() <= n {
+                    stats.leaf_size_histogram.resize(n + 1, 0);
+                }
+                stats.leaf_size_histogram[n] += 1;
+
+                LinearNode {bbox, offset: first_object as u32, object_count: n as u32, axis}
+            }
+        };
+
+        node_index
+    }
+}
+
+impl Hittable for LinearBvh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inv_direction = Vec3::ONE / ray.direction();
+        let mut closest = t_max;
+        let mut hit_record = None;
+
+        let mut to_visit = [0usize; MAX_DEPTH];
+        let mut to_visit_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            let mut visit_next = true;
+            if node.bbox.hit_precomputed(ray.origin(), &inv_direction, t_min, closest) {
+                if node.object_count > 0 {
+                    let first = node.offset as usize;
+                    for object in &self.objects[first..first + node.object_count as usize] {
+                        if let Some(hr) = object.hit(ray, t_min, closest) {
+                            closest = hr.t;
+                            hit_record = Some(hr);
+                        }
+                    }
+                } else {
+                    // Visit first the child closer to the ray origin, so that 
+                    // hits found there can cull the farther one
+                    let (near, far) = if inv_direction.component(node.axis) < 0. {
+                        (node.offset as usize, current + 1)
+                    } else {
+                        (current + 1, node.offset as usize)
+                    };
+                    to_visit[to_visit_len] = far;
+                    to_visit_len += 1;
+                    current = near;
+                    visit_next = false;
+                }
+            }
+
+            if visit_next {
+                if to_visit_len == 0 {
+                    break;
+                }
+                to_visit_len -= 1;
+                current = to_visit[to_visit_len];
+            }
+        }
+
+        hit_record
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| node.bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::hittable::{self, fixtures::{spheres, random_spheres}};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn leaves_hold_every_object_once() {
+        let params = (0..1000)
+            .map(|i| (Vec3::new((i % 10) as f64, (i / 10 % 10) as f64, (i / 100) as f64), 0.3))
+            .collect::<Vec<_>>();
+        let bvh = LinearBvh::new(spheres(&params));
+        let stats = bvh.stats();
+
+        let objects_in_leaves: usize = stats.leaf_size_histogram.iter().enumerate().map(|(size, count)| size * count).sum();
+        assert_eq!(objects_in_leaves, 1000);
+        assert_eq!(stats.leaf_count, stats.leaf_size_histogram.iter().sum::<usize>());
+        assert_eq!(stats.node_count, 2 * stats.leaf_count - 1);
+        assert!(stats.max_depth < MAX_DEPTH);
+    }
+
+    #[test]
+    fn big_leaves_keep_every_object() {
+        // With all the centers in the same spot everything ends up in one leaf, 
+        // the biggest sphere is the last one and the first one a ray hits
+        let params = (0..70_000)
+            .map(|i| (Vec3::ZERO, 1. + i as f64 * 1e-4))
+            .collect::<Vec<_>>();
+        let bvh = LinearBvh::new(spheres(&params));
+        assert_eq!(bvh.stats().leaf_count, 1);
+
+        let ray = Ray::new(Vec3::new(0., 0., 20.), Vec3::new(0., 0., -1.));
+        let hit = bvh.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((hit.t - (20. - params[69_999].1)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn same_hits_as_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let params = random_spheres(&mut rng, 500);
+
+        let bvh = LinearBvh::new(spheres(&params));
+        let objects = spheres(&params);
+
+        for _ in 0..1000 {
+            let origin = Vec3::new(rng.gen_range(-30. ..30.), rng.gen_range(-30. ..30.), 30.);
+            let target = Vec3::new(rng.gen_range(-10. ..10.), rng.gen_range(-10. ..10.), 0.);
+            let ray = Ray::new(origin, target - origin);
+
+            let from_bvh = bvh.hit(&ray, 0.001, f64::INFINITY).map(|hr| hr.t);
+            let from_list = hittable::hits(&objects, &ray, 0.001, f64::INFINITY).map(|hr| hr.t);
+            assert_eq!(from_bvh, from_list);
+        }
+    }
+}
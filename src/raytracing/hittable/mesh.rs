@@ -0,0 +1,238 @@
+use crate::{
+    vec3::Vec3, 
+    raytracing::{
+        Ray, 
+        Hittable, 
+        hittable::{HitRecord, Aabb, triangle}, 
+        Material
+    }
+};
+use std::sync::Arc;
+
+
+/*
+    A triangle mesh where vertices are stored once and shared between the 
+    triangles that use them. Every vertex has a position and optionally a 
+    normal, texture coordinates and a color, all found at the same index. 
+    All the triangles share the same material.
+*/
+pub struct TriangleMesh {
+    positions: Vec<Vec3>, 
+    normals: Option<Vec<Vec3>>, 
+    uvs: Option<Vec<(f64, f64)>>, 
+    colors: Option<Vec<Vec3>>, 
+    indices: Vec<[usize; 3]>, 
+
+    material: Arc<Box<dyn Material + Send + Sync>>, 
+    smooth_shading: bool
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vec3>, 
+        indices: Vec<[usize; 3]>, 
+        material: Arc<Box<dyn Material + Send + Sync>>
+    ) -> TriangleMesh {
+        assert!(
+            indices.iter().flatten().all(|i| *i < positions.len()), 
+            "Triangle mesh indices must refer to existing vertices"
+        );
+
+        TriangleMesh {
+            positions, 
+            normals: None, 
+            uvs: None, 
+            colors: None, 
+            indices, 
+            material, 
+            smooth_shading: false
+        }
+    }
+
+    /*
+        Per-vertex normals, used instead of the flat normal of each 
+        triangle when smooth shading is enabled. Enables smooth shading
+    */
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> TriangleMesh {
+        assert_eq!(normals.len(), self.positions.len(), "Triangle mesh needs one normal per vertex");
+        self.normals = Some(normals);
+        self.smooth_shading = true;
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<(f64, f64)>) -> TriangleMesh {
+        assert_eq!(uvs.len(), self.positions.len(), "Triangle mesh needs one uv per vertex");
+        self.uvs = Some(uvs);
+        self
+    }
+
+    pub fn with_colors(mut self, colors: Vec<Vec3>) -> TriangleMesh {
+        assert_eq!(colors.len(), self.positions.len(), "Triangle mesh needs one color per vertex");
+        self.colors = Some(colors);
+        self
+    }
+
+    pub fn with_smooth_shading(mut self, smooth_shading: bool) -> TriangleMesh {
+        self.smooth_shading = smooth_shading;
+        self
+    }
+
+    /*
+        Scales the mesh around the origin and then moves it by offset
+    */
+    pub fn transformed(mut self, scale: f64, offset: Vec3) -> TriangleMesh {
+        assert!(scale > 0., "Triangle mesh scale must be positive");
+        for p in self.positions.iter_mut() {
+            *p = *p * scale + offset;
+        }
+        self
+    }
+
+    pub fn positions(&self) -> &[Vec3] { &self.positions }
+    pub fn normals(&self) -> Option<&[Vec3]> { self.normals.as_deref() }
+    pub fn uvs(&self) -> Option<&[(f64, f64)]> { self.uvs.as_deref() }
+    pub fn colors(&self) -> Option<&[Vec3]> { self.colors.as_deref() }
+    pub fn indices(&self) -> &[[usize; 3]] { &self.indices }
+    pub fn smooth_shading(&self) -> bool { self.smooth_shading }
+    pub fn triangle_count(&self) -> usize { self.indices.len() }
+    pub fn material(&self) -> &Arc<Box<dyn Material + Send + Sync>> { &self.material }
+
+    fn vertices(&self, triangle: usize) -> [Vec3; 3] {
+        let [a, b, c] = self.indices[triangle];
+        [self.positions[a], self.positions[b], self.positions[c]]
+    }
+
+    /*
+        One Hittable per triangle, all sharing the mesh, so that they can 
+        be put in a BVH together with the rest of the world
+    */
+    pub fn into_hittables(self) -> Vec<Box<dyn Hittable + Send + Sync>> {
+        let mesh = Arc::new(self);
+        (0..mesh.triangle_count())
+            .map(|index| Box::new(MeshTriangle {mesh: mesh.clone(), index}) as Box<dyn Hittable + Send + Sync>)
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+pub struct MeshTriangle {
+    mesh: Arc<TriangleMesh>, 
+    index: usize
+}
+
+impl MeshTriangle {
+    pub fn mesh(&self) -> &Arc<TriangleMesh> { &self.mesh }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let vertices = self.mesh.vertices(self.index);
+        let (t, b1, b2) = triangle::intersect(&vertices, ray, t_min, t_max)?;
+        let b0 = 1. - b1 - b2;
+
+        let [p0, p1, p2] = &vertices;
+        let outward_normal = Vec3::cross(&(p1 - p0), &(p2 - p0)).unit_vector();
+        let mut hit_record = HitRecord::new(self.mesh.material.clone(), ray, t, outward_normal);
+        hit_record.barycentric = (b1, b2);
+
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        hit_record.uv = match &self.mesh.uvs {
+            Some(uvs) => (
+                b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0, 
+                b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1
+            ), 
+            None => (b1, b2)
+        };
+
+        if let Some(colors) = &self.mesh.colors {
+            hit_record.color = Some(b0 * colors[i0] + b1 * colors[i1] + b2 * colors[i2]);
+        }
+
+        if let (true, Some(normals)) = (self.mesh.smooth_shading, &self.mesh.normals) {
+            let shading_normal = b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2];
+            // Interpolating normals pointing in opposite directions can 
+            // cancel them out, keep the flat normal in that case
+            if !shading_normal.near_zero() {
+                let shading_normal = shading_normal.unit_vector();
+                // The shading normal must be on the same side of the 
+                // surface as the geometric one
+                let shading_normal = if Vec3::dot(&shading_normal, &outward_normal) < 0. { -shading_normal } else { shading_normal };
+                hit_record.normal = if hit_record.front_face { shading_normal } else { -shading_normal };
+            }
+        }
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle::bounding_box(&self.mesh.vertices(self.index))
+    }
+
+    fn sample(&self, origin: &Vec3, u: (f64, f64)) -> Option<(HitRecord, f64)> {
+        let (ray, pdf) = triangle::sample_towards(&self.mesh.vertices(self.index), origin, u)?;
+        Some((self.hit(&ray, 0., f64::INFINITY)?, pdf))
+    }
+
+    fn pdf(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        triangle::pdf_towards(&self.mesh.vertices(self.index), origin, direction)
+    }
+
+    fn as_light(&self) -> Option<Box<dyn Hittable + Send + Sync>> {
+        match self.mesh.material.emits_light() {
+            true => Some(Box::new(self.clone())), 
+            false => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material;
+
+    // A unit square in the z = 0 plane made of two triangles
+    fn square() -> TriangleMesh {
+        let material: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(
+            material::Lambertian::new(Vec3::ONE)
+        ));
+        TriangleMesh::new(
+            vec![Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), Vec3::new(1., 1., 0.), Vec3::new(0., 1., 0.)], 
+            vec![[0, 1, 2], [0, 2, 3]], 
+            material
+        )
+    }
+
+    fn hit(mesh: TriangleMesh, x: f64, y: f64) -> Option<HitRecord> {
+        let ray = Ray::new(Vec3::new(x, y, 1.), Vec3::new(0., 0., -1.));
+        mesh.into_hittables().hit(&ray, 0., f64::INFINITY)
+    }
+
+    #[test]
+    fn hits_every_triangle() {
+        assert!(hit(square(), 0.75, 0.25).is_some());
+        assert!(hit(square(), 0.25, 0.75).is_some());
+        assert!(hit(square(), 1.25, 0.75).is_none());
+    }
+
+    #[test]
+    fn interpolates_uvs() {
+        let mesh = square().with_uvs(vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)]);
+        let hr = hit(mesh, 0.75, 0.25).unwrap();
+        assert!((hr.uv.0 - 0.75).abs() < 1e-12);
+        assert!((hr.uv.1 - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn smooth_shading_interpolates_normals() {
+        let tilted = Vec3::new(1., 0., 1.).unit_vector();
+        let up = Vec3::new(0., 0., 1.);
+        let mesh = square().with_normals(vec![up, tilted, tilted, up]);
+
+        let hr = hit(mesh, 0.5, 0.5).unwrap();
+        assert!(hr.normal.x > 0. && hr.normal.z > 0.);
+        assert!((hr.normal.length() - 1.).abs() < 1e-12);
+
+        let flat = square().with_normals(vec![up, tilted, tilted, up]).with_smooth_shading(false);
+        assert_eq!(hit(flat, 0.5, 0.5).unwrap().normal, up);
+    }
+}
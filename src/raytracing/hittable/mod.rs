@@ -2,16 +2,27 @@ use crate::{
     vec3::Vec3, 
     raytracing::{
         ray::Ray, 
-        Material
+        Material, 
+        AsAny
     }
 };
 use std::sync::Arc;
 
 
 pub mod sphere;
+pub mod triangle;
+pub mod mesh;
+pub mod aabb;
+pub mod bvh;
+pub mod linear_bvh;
 
 pub use {
-    sphere::Sphere
+    sphere::Sphere, 
+    triangle::Triangle, 
+    mesh::{TriangleMesh, MeshTriangle}, 
+    aabb::Aabb, 
+    bvh::BvhNode, 
+    linear_bvh::LinearBvh
 };
 
 pub struct HitRecord {
@@ -20,7 +31,16 @@ pub struct HitRecord {
     pub t: f64, 
 
     pub front_face: bool, 
-    pub material: Arc<Box<dyn Material + Send + Sync>>
+    pub material: Arc<Box<dyn Material + Send + Sync>>, 
+
+    // Texture coordinates of the hit point on the surface
+    pub uv: (f64, f64), 
+    // For triangles, the weights of the second and third vertex in the hit 
+    // point, the first one is 1 minus the other two. Zero for other shapes
+    pub barycentric: (f64, f64), 
+    // For meshes with colored vertices, the color at the hit point. 
+    // It tints the albedo of lambertian materials
+    pub color: Option<Vec3>
 }
 
 impl HitRecord {
@@ -31,13 +51,44 @@ impl HitRecord {
             p: (ray.at(root)),  
             t: root, 
             front_face, 
-            normal: if front_face { outward_normal } else { -outward_normal }
+            normal: if front_face { outward_normal } else { -outward_normal }, 
+            uv: (0., 0.), 
+            barycentric: (0., 0.), 
+            color: None
         }
     }
 }
 
-pub trait Hittable {
+pub trait Hittable: AsAny {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn bounding_box(&self) -> Aabb;
+
+    /*
+        Picks a point of the surface that can be seen from 'origin', given 
+        two uniform random numbers. Returns the hit record of the ray going 
+        from 'origin' to that point, and the probability density of picking 
+        its direction over the sphere of directions around 'origin'. 
+        Used to aim at lights, shapes that return None can't be lights
+    */
+    fn sample(&self, _origin: &Vec3, _u: (f64, f64)) -> Option<(HitRecord, f64)> {
+        None
+    }
+
+    /*
+        The probability density of sample picking 'direction' from 'origin', 
+        zero when the direction misses the object
+    */
+    fn pdf(&self, _origin: &Vec3, _direction: &Vec3) -> f64 {
+        0.
+    }
+
+    /*
+        A copy of the object to be sampled as a light, if its material 
+        emits light
+    */
+    fn as_light(&self) -> Option<Box<dyn Hittable + Send + Sync>> {
+        None
+    }
 }
 
 pub fn hits(hittable_objects: &Vec<Box<dyn Hittable + Send + Sync>>, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
@@ -53,3 +104,39 @@ pub fn hits(hittable_objects: &Vec<Box<dyn Hittable + Send + Sync>>, ray: &Ray,
 
     hit_record
 }
+
+impl Hittable for Vec<Box<dyn Hittable + Send + Sync>> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hits(self, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.iter().fold(Aabb::EMPTY, |acc, object| Aabb::surrounding(&acc, &object.bounding_box()))
+    }
+}
+
+// Scenes shared by the tests of the acceleration structures
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+    use crate::raytracing::material;
+    use rand::{Rng, rngs::StdRng};
+
+    pub fn spheres(params: &[(Vec3, f64)]) -> Vec<Box<dyn Hittable + Send + Sync>> {
+        let material: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(
+            material::Lambertian::new(Vec3::ONE)
+        ));
+
+        params.iter().map(|(center, radius)| {
+            Box::new(Sphere::new(*center, *radius, material.clone())) as Box<dyn Hittable + Send + Sync>
+        }).collect()
+    }
+
+    // Centers and radii of 'count' spheres scattered in a 20x20x20 cube
+    pub fn random_spheres(rng: &mut StdRng, count: usize) -> Vec<(Vec3, f64)> {
+        (0..count).map(|_| {
+            let center = Vec3::new(rng.gen_range(-10. ..10.), rng.gen_range(-10. ..10.), rng.gen_range(-10. ..10.));
+            (center, rng.gen_range(0.1..1.))
+        }).collect()
+    }
+}
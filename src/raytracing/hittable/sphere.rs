@@ -3,13 +3,17 @@ use crate::{
     raytracing::{
         Ray, 
         Hittable, 
-        hittable::HitRecord, 
+        hittable::{HitRecord, Aabb}, 
         Material
     }
 };
-use std::sync::Arc;
+use std::{
+    sync::Arc, 
+    f64::consts::PI
+};
 
 
+#[derive(Clone)]
 pub struct Sphere {
     center: Vec3, 
     radius: f64, 
@@ -21,6 +25,36 @@ impl Sphere {
     pub fn new(center: Vec3, radius: f64, material: Arc<Box<dyn Material + Send + Sync>>) -> Sphere {
         Sphere {center, radius, material}
     }
+
+    pub fn center(&self) -> &Vec3 { &self.center }
+    pub fn radius(&self) -> f64 { self.radius }
+    pub fn material(&self) -> &Arc<Box<dyn Material + Send + Sync>> { &self.material }
+
+    /*
+        Seen from outside the sphere covers a cone of directions around 
+        the one to its center, returns 1 - cos(theta_max) for its half 
+        angle written so that it doesn't round to 0 for spheres far away
+    */
+    fn cone(&self, origin: &Vec3) -> Option<f64> {
+        let distance_squared = (self.center - origin).length_squared();
+        let sin_theta_max_squared = self.radius * self.radius / distance_squared;
+        if sin_theta_max_squared >= 1. {
+            return None;
+        }
+        Some(sin_theta_max_squared / (1. + (1. - sin_theta_max_squared).sqrt()))
+    }
+
+    /*
+        The density over the sphere of directions of picking a point 
+        uniformly over the area, for the point 'ray' hits at 't'
+    */
+    fn area_pdf(&self, ray: &Ray, t: f64) -> f64 {
+        let to_point = t * ray.direction();
+        let normal = (ray.at(t) - self.center).unit_vector();
+        let cosine = Vec3::dot(&normal, &to_point.unit_vector()).abs();
+        let area = 4. * PI * self.radius * self.radius;
+        to_point.length_squared() / (cosine * area)
+    }
 }
 
 impl Hittable for Sphere {
@@ -62,16 +96,80 @@ impl Hittable for Sphere {
                 }
             }
         };
-        if root.is_none() { return None; }
-        let root = root.unwrap();
+        let root = root?;
 
-        let hit_record = HitRecord::new(
+        let outward_normal = (ray.at(root) - self.center) / self.radius;
+        let mut hit_record = HitRecord::new(
             self.material.clone(), 
-            &ray,
+            ray,
             root, 
-            (ray.at(root) - self.center) / self.radius,  
+            outward_normal,  
         );
 
+        // Longitude and latitude of the hit point, both mapped to [0, 1]
+        let theta = (-outward_normal.y).acos();
+        let phi = (-outward_normal.z).atan2(outward_normal.x) + PI;
+        hit_record.uv = (phi / (2. * PI), theta / PI);
+
         Some(hit_record)
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::from_float(self.radius.abs());
+        Aabb::new(self.center - r, self.center + r)
+    }
+
+    fn sample(&self, origin: &Vec3, u: (f64, f64)) -> Option<(HitRecord, f64)> {
+        let radius = self.radius.abs();
+        let phi = 2. * PI * u.1;
+
+        let one_minus_cos_theta_max = match self.cone(origin) {
+            Some(one_minus_cos_theta_max) => one_minus_cos_theta_max, 
+            /*
+                From inside every point of the sphere can be seen: pick one 
+                uniformly over its area
+            */
+            None => {
+                let z = 1. - 2. * u.0;
+                let r = (1. - z * z).max(0.).sqrt();
+                let point = self.center + radius * Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                let ray = Ray::new(*origin, point - origin);
+                return Some((self.hit(&ray, 0., f64::INFINITY)?, self.area_pdf(&ray, 1.)));
+            }
+        };
+
+        // From outside pick a direction uniformly inside the cone
+        let one_minus_cos_theta = u.0 * one_minus_cos_theta_max;
+        let cos_theta = 1. - one_minus_cos_theta;
+        let sin_theta = (one_minus_cos_theta * (2. - one_minus_cos_theta)).max(0.).sqrt();
+
+        let w = (self.center - origin).unit_vector();
+        let a = if w.x.abs() > 0.9 { Vec3::new(0., 1., 0.) } else { Vec3::new(1., 0., 0.) };
+        let v = Vec3::cross(&w, &a).unit_vector();
+        let u = Vec3::cross(&w, &v);
+        let direction = sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * w;
+
+        let hit_record = self.hit(&Ray::new(*origin, direction), 0., f64::INFINITY)?;
+        Some((hit_record, 1. / (2. * PI * one_minus_cos_theta_max)))
+    }
+
+    fn pdf(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        let hit_record = match self.hit(&ray, 0., f64::INFINITY) {
+            Some(hit_record) => hit_record, 
+            None => return 0.
+        };
+
+        match self.cone(origin) {
+            Some(one_minus_cos_theta_max) => 1. / (2. * PI * one_minus_cos_theta_max), 
+            None => self.area_pdf(&ray, hit_record.t)
+        }
+    }
+
+    fn as_light(&self) -> Option<Box<dyn Hittable + Send + Sync>> {
+        match self.material.emits_light() {
+            true => Some(Box::new(self.clone())), 
+            false => None
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,213 @@
+use crate::{
+    vec3::Vec3, 
+    raytracing::{
+        Ray, 
+        Hittable, 
+        hittable::{HitRecord, Aabb}, 
+        Material
+    }
+};
+use std::sync::Arc;
+
+
+#[derive(Clone)]
+pub struct Triangle {
+    vertices: [Vec3; 3], 
+    material: Arc<Box<dyn Material + Send + Sync>>
+}
+
+impl Triangle {
+    /*
+        The front face of the triangle is the one where the vertices 
+        appear in counter-clockwise order
+    */
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: Arc<Box<dyn Material + Send + Sync>>) -> Triangle {
+        Triangle {vertices: [v0, v1, v2], material}
+    }
+
+    pub fn vertices(&self) -> &[Vec3; 3] { &self.vertices }
+    pub fn material(&self) -> &Arc<Box<dyn Material + Send + Sync>> { &self.material }
+}
+
+/*
+    Möller–Trumbore ray-triangle intersection: solves 
+        origin + t * direction = (1 - b1 - b2) * p0 + b1 * p1 + b2 * p2
+    for t and the barycentric coordinates (b1, b2) with Cramer's rule. 
+    The point is inside the triangle when b1, b2 and 1 - b1 - b2 are all 
+    non-negative. Returns (t, b1, b2)
+*/
+pub fn intersect(vertices: &[Vec3; 3], ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let [p0, p1, p2] = vertices;
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+
+    let pvec = Vec3::cross(ray.direction(), &edge2);
+    let determinant = Vec3::dot(&edge1, &pvec);
+    // The ray is parallel to the plane of the triangle, or the triangle is degenerate
+    if determinant == 0. || !determinant.is_finite() {
+        return None;
+    }
+    let inv_determinant = 1. / determinant;
+
+    let tvec = ray.origin() - p0;
+    let b1 = Vec3::dot(&tvec, &pvec) * inv_determinant;
+    if !(0. ..=1.).contains(&b1) {
+        return None;
+    }
+
+    let qvec = Vec3::cross(&tvec, &edge1);
+    let b2 = Vec3::dot(ray.direction(), &qvec) * inv_determinant;
+    if b2 < 0. || b1 + b2 > 1. {
+        return None;
+    }
+
+    let t = Vec3::dot(&edge2, &qvec) * inv_determinant;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/*
+    The box around the three vertices, slightly enlarged so that triangles 
+    lying on an axis-aligned plane don't get a box with no thickness
+*/
+pub fn bounding_box(vertices: &[Vec3; 3]) -> Aabb {
+    const PADDING: f64 = 1e-9;
+
+    let bbox = vertices.iter().fold(Aabb::EMPTY, |acc, v| acc.including(v));
+    Aabb::new(bbox.minimum - PADDING, bbox.maximum + PADDING)
+}
+
+/*
+    The density over the sphere of directions around 'origin' of picking 
+    a point uniformly over the area of the triangle, for the point where 
+    'ray' leaving from 'origin' hits it at 't'. An area turns into a smaller 
+    solid angle the further away and the more tilted it is
+*/
+fn solid_angle_pdf(vertices: &[Vec3; 3], ray: &Ray, t: f64) -> f64 {
+    let [p0, p1, p2] = vertices;
+    let cross = Vec3::cross(&(p1 - p0), &(p2 - p0));
+    let area = 0.5 * cross.length();
+
+    let to_point = t * ray.direction();
+    let distance_squared = to_point.length_squared();
+    let cosine = Vec3::dot(&cross, &to_point).abs() / (2. * area * distance_squared.sqrt());
+    if area == 0. || cosine == 0. || !cosine.is_finite() {
+        return 0.;
+    }
+    distance_squared / (cosine * area)
+}
+
+/*
+    Picks a point uniformly over the area of the triangle and returns the 
+    ray from 'origin' to it, reaching it at t = 1, along with the density 
+    of that direction over the sphere of directions around 'origin'
+*/
+pub fn sample_towards(vertices: &[Vec3; 3], origin: &Vec3, u: (f64, f64)) -> Option<(Ray, f64)> {
+    let [p0, p1, p2] = vertices;
+    let s = u.0.sqrt();
+    let (b1, b2) = (s * (1. - u.1), s * u.1);
+    let point = p0 + b1 * (p1 - p0) + b2 * (p2 - p0);
+
+    let ray = Ray::new(*origin, point - origin);
+    let pdf = solid_angle_pdf(vertices, &ray, 1.);
+    if pdf == 0. {
+        return None;
+    }
+    Some((ray, pdf))
+}
+
+pub fn pdf_towards(vertices: &[Vec3; 3], origin: &Vec3, direction: &Vec3) -> f64 {
+    let ray = Ray::new(*origin, *direction);
+    match intersect(vertices, &ray, 0., f64::INFINITY) {
+        Some((t, _, _)) => solid_angle_pdf(vertices, &ray, t), 
+        None => 0.
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, b1, b2) = intersect(&self.vertices, ray, t_min, t_max)?;
+
+        let [p0, p1, p2] = &self.vertices;
+        let outward_normal = Vec3::cross(&(p1 - p0), &(p2 - p0)).unit_vector();
+        let mut hit_record = HitRecord::new(self.material.clone(), ray, t, outward_normal);
+        hit_record.uv = (b1, b2);
+        hit_record.barycentric = (b1, b2);
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        bounding_box(&self.vertices)
+    }
+
+    fn sample(&self, origin: &Vec3, u: (f64, f64)) -> Option<(HitRecord, f64)> {
+        let (ray, pdf) = sample_towards(&self.vertices, origin, u)?;
+        Some((self.hit(&ray, 0., f64::INFINITY)?, pdf))
+    }
+
+    fn pdf(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        pdf_towards(&self.vertices, origin, direction)
+    }
+
+    fn as_light(&self) -> Option<Box<dyn Hittable + Send + Sync>> {
+        match self.material.emits_light() {
+            true => Some(Box::new(self.clone())), 
+            false => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::material;
+
+    fn triangle() -> Triangle {
+        let material: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(
+            material::Lambertian::new(Vec3::ONE)
+        ));
+        Triangle::new(
+            Vec3::new(0., 0., 0.), 
+            Vec3::new(1., 0., 0.), 
+            Vec3::new(0., 1., 0.), 
+            material
+        )
+    }
+
+    #[test]
+    fn hit_has_barycentric_coordinates() {
+        let ray = Ray::new(Vec3::new(0.25, 0.5, 1.), Vec3::new(0., 0., -1.));
+        let hr = triangle().hit(&ray, 0., f64::INFINITY).unwrap();
+
+        assert!((hr.t - 1.).abs() < 1e-12);
+        assert!((hr.barycentric.0 - 0.25).abs() < 1e-12);
+        assert!((hr.barycentric.1 - 0.5).abs() < 1e-12);
+        assert!(hr.front_face);
+        assert_eq!(hr.normal, Vec3::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn back_face_flips_the_normal() {
+        let ray = Ray::new(Vec3::new(0.25, 0.25, -1.), Vec3::new(0., 0., 1.));
+        let hr = triangle().hit(&ray, 0., f64::INFINITY).unwrap();
+
+        assert!(!hr.front_face);
+        assert_eq!(hr.normal, Vec3::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn misses() {
+        let t = triangle();
+        // Outside the edges
+        assert!(t.hit(&Ray::new(Vec3::new(0.6, 0.6, 1.), Vec3::new(0., 0., -1.)), 0., f64::INFINITY).is_none());
+        assert!(t.hit(&Ray::new(Vec3::new(-0.1, 0.5, 1.), Vec3::new(0., 0., -1.)), 0., f64::INFINITY).is_none());
+        // Parallel to the triangle
+        assert!(t.hit(&Ray::new(Vec3::new(0.2, 0.2, 0.), Vec3::new(1., 0., 0.)), 0., f64::INFINITY).is_none());
+        // Behind the ray
+        assert!(t.hit(&Ray::new(Vec3::new(0.2, 0.2, -1.), Vec3::new(0., 0., -1.)), 0., f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::vec3::Vec3;
+use super::{
+    hittable, 
+    Ray, 
+    World, 
+    RenderSettings, 
+    Sampler
+};
+
+
+/*
+    A path of light followed from the camera, one bounce at a time
+*/
+struct PathState {
+    ray: Ray, 
+    // How much of the light found from here on reaches the camera
+    throughput: Vec3, 
+    // The light that reached the camera so far
+    radiance: Vec3, 
+    // The bounces made so far
+    depth: u32, 
+    /*
+        The density the material the ray left from had of picking its 
+        direction, None for camera rays and rays leaving glass or mirrors. 
+        Light found by the other rays was also looked for by aiming at it 
+        from where they left, the two estimates are combined with the 
+        power heuristic
+    */
+    bsdf_pdf: Option<f64>
+}
+
+/*
+    What a camera ray found: the light coming along it, and the surface it 
+    hit first for the extra layers of the image. Rays that hit nothing have 
+    a black normal and albedo and an infinite depth
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathSample {
+    pub radiance: Vec3, 
+    pub normal: Vec3, 
+    pub albedo: Vec3, 
+    // The distance from the camera
+    pub depth: f64
+}
+
+/*
+    The light coming towards the origin of 'ray' along it
+*/
+pub fn ray_color(ray: Ray, world: &World, settings: &RenderSettings, sampler: &mut dyn Sampler) -> PathSample {
+    let mut path = PathState {
+        ray, 
+        throughput: Vec3::ONE, 
+        radiance: Vec3::ZERO, 
+        depth: 0, 
+        bsdf_pdf: None
+    };
+    let mut sample = PathSample {
+        radiance: Vec3::ZERO, 
+        normal: Vec3::ZERO, 
+        albedo: Vec3::ZERO, 
+        depth: f64::INFINITY
+    };
+
+    loop {
+        let hit_record = match world.objects.hit(&path.ray, 0.001, f64::INFINITY) {
+            Some(hit_record) => hit_record, 
+            None => {
+                let direction = path.ray.direction();
+                let mut color = world.background.color(direction);
+                if let Some(bsdf_pdf) = path.bsdf_pdf {
+                    color = color * power_heuristic(bsdf_pdf, world.background.pdf(direction));
+                }
+                path.radiance += path.throughput * color;
+                break;
+            }
+        };
+
+        let material = hit_record.material.as_ref();
+        if path.depth == 0 {
+            sample.normal = hit_record.normal;
+            sample.albedo = material.base_color(&hit_record);
+            sample.depth = (hit_record.p - path.ray.origin()).length();
+        }
+
+        let mut emitted = material.emitted(&hit_record);
+        if let (Some(bsdf_pdf), true) = (path.bsdf_pdf, material.emits_light()) {
+            emitted = emitted * power_heuristic(bsdf_pdf, world.lights.pdf(path.ray.origin(), path.ray.direction()));
+        }
+        path.radiance += path.throughput * emitted;
+
+        /*
+            Checked before aiming at the lights: the light found that way is 
+            weighted against the one the scattered ray would find, and past 
+            the last bounce there is no scattered ray to make up the rest
+        */
+        if path.depth >= settings.max_depth {
+            break;
+        }
+        path.radiance += path.throughput * direct_light(&path.ray, &hit_record, world, sampler);
+
+        let (attenuation, scattered) = match material.scatter(&path.ray, &hit_record, sampler) {
+            Some(scattered) => scattered, 
+            None => break
+        };
+        path.throughput *= attenuation;
+
+        /*
+            Russian roulette: the less light the path can still carry the 
+            more likely it is to stop here, the paths that survive with 
+            probability q count 1/q times as much
+        */
+        if path.depth + 1 >= settings.roulette_depth {
+            let survival = path.throughput.x.max(path.throughput.y).max(path.throughput.z).min(0.95);
+            if survival <= 0. || sampler.get_1d() >= survival {
+                break;
+            }
+            path.throughput = path.throughput / survival;
+        }
+
+        let pdf = material.pdf(&path.ray, &hit_record, scattered.direction());
+        path.bsdf_pdf = Some(pdf).filter(|pdf| *pdf > 0.);
+        path.ray = scattered;
+        path.depth += 1;
+    }
+
+    PathSample {radiance: path.radiance, ..sample}
+}
+
+/*
+    Weight of an estimate made with a strategy that had density 'pdf' of 
+    picking its direction, when another strategy had density 'other_pdf'
+*/
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if b.is_infinite() { 0. } else { a / (a + b) }
+}
+
+/*
+    The light reaching the hit point straight from one of the lights and 
+    from the background, found by aiming at them. Scattered rays rarely 
+    find something as small as the sun, but they do better on glossy 
+    surfaces and big lights, so both estimates are weighted
+*/
+fn direct_light(
+    ray_in: &Ray, 
+    hit_record: &hittable::HitRecord, 
+    world: &World, 
+    sampler: &mut dyn Sampler
+) -> Vec3 {
+    let material = hit_record.material.as_ref();
+    let mut direct = Vec3::ZERO;
+
+    if let Some((direction, color, light_pdf)) = world.background.sample(sampler.get_2d()) {
+        let f = material.eval(ray_in, hit_record, &direction);
+        if f != Vec3::ZERO && visible(world.objects.as_ref(), hit_record, &direction, f64::INFINITY) {
+            let weight = power_heuristic(light_pdf, material.pdf(ray_in, hit_record, &direction));
+            direct += f * color * (weight / light_pdf);
+        }
+    }
+
+    if let Some((light, light_pdf)) = world.lights.sample(&hit_record.p, sampler.get_1d(), sampler.get_2d()) {
+        let to_light = light.p - hit_record.p;
+        let distance = to_light.length();
+        let direction = to_light / distance;
+
+        let f = material.eval(ray_in, hit_record, &direction);
+        if f != Vec3::ZERO && visible(world.objects.as_ref(), hit_record, &direction, distance - 0.001) {
+            let weight = power_heuristic(light_pdf, material.pdf(ray_in, hit_record, &direction));
+            direct += f * light.material.emitted(&light) * (weight / light_pdf);
+        }
+    }
+
+    direct
+}
+
+/*
+    Whether nothing is in the way going from the hit point along 'direction' 
+    up to 'distance', and the direction leaves from the visible side of 
+    the surface
+*/
+fn visible(world: &(dyn hittable::Hittable + Send + Sync), hit_record: &hittable::HitRecord, direction: &Vec3, distance: f64) -> bool {
+    Vec3::dot(&hit_record.normal, direction) > 0. 
+        && world.hit(&Ray::new(hit_record.p, *direction), 0.001, distance).is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::{
+        Material, 
+        material, 
+        LightList, 
+        background::SolidColor, 
+        hittable::{Hittable, Sphere, Triangle}
+    };
+    use crate::raytracing::sampler::Independent;
+    use std::sync::Arc;
+
+    /*
+        A spherical light of radius r at height h above a diffuse floor gives 
+        it an irradiance of pi * emit * r^2 / h^2, the floor right below 
+        it looks albedo * emit * r^2 / h^2 bright
+    */
+    fn floor_brightness(radius: f64, height: f64) -> (f64, f64) {
+        let floor: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::Lambertian::new(Vec3::from_float(0.5))));
+        let light: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::DiffuseLight::new(Vec3::from_float(4.))));
+
+        let objects: Vec<Box<dyn Hittable + Send + Sync>> = vec![
+            Box::new(Triangle::new(Vec3::new(-1e4, 0., 1e4), Vec3::new(1e4, 0., 1e4), Vec3::new(0., 0., -1e4), floor)), 
+            Box::new(Sphere::new(Vec3::new(0., height, 0.), radius, light))
+        ];
+        let world = World {
+            lights: LightList::from_objects(&objects), 
+            objects: Box::new(objects), 
+            background: Box::new(SolidColor::new(Vec3::ZERO))
+        };
+
+        // Looking straight down at the floor from below the light
+        let origin = Vec3::new(0., 0.5 * (height - radius), 0.);
+        let n = 20000;
+        let settings = RenderSettings::default();
+        let mut sampler = Independent::new(1);
+        let total = (0..n).fold(0., |total, i| {
+            sampler.start_sample(0, i);
+            total + ray_color(Ray::new(origin, Vec3::new(0., -1., 0.)), &world, &settings, &mut sampler).radiance.x
+        });
+
+        (total / n as f64, 0.5 * 4. * radius * radius / (height * height))
+    }
+
+    #[test]
+    fn small_and_big_lights_converge() {
+        for (radius, height) in [(0.01, 2.), (0.5, 1.), (3., 3.5)].iter() {
+            let (estimate, expected) = floor_brightness(*radius, *height);
+            assert!((estimate / expected - 1.).abs() < 0.03, "radius {}: {} instead of {}", radius, estimate, expected);
+        }
+    }
+
+    #[test]
+    fn no_light_is_looked_for_past_the_last_bounce() {
+        let floor: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::Lambertian::new(Vec3::from_float(0.5))));
+        let light: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::DiffuseLight::new(Vec3::from_float(4.))));
+        let objects: Vec<Box<dyn Hittable + Send + Sync>> = vec![
+            Box::new(Triangle::new(Vec3::new(-1e4, 0., 1e4), Vec3::new(1e4, 0., 1e4), Vec3::new(0., 0., -1e4), floor)), 
+            Box::new(Sphere::new(Vec3::new(0., 2., 0.), 0.5, light))
+        ];
+        let world = World {
+            lights: LightList::from_objects(&objects), 
+            objects: Box::new(objects), 
+            background: Box::new(SolidColor::new(Vec3::ZERO))
+        };
+
+        // Without bounces only the lights seen straight away count
+        let settings = RenderSettings {max_depth: 0, ..RenderSettings::default()};
+        let mut sampler = Independent::new(1);
+        let origin = Vec3::new(0., 0.5, 0.);
+        let down = ray_color(Ray::new(origin, Vec3::new(0., -1., 0.)), &world, &settings, &mut sampler);
+        let up = ray_color(Ray::new(origin, Vec3::new(0., 1., 0.)), &world, &settings, &mut sampler);
+        assert_eq!(down.radiance, Vec3::ZERO);
+        assert_eq!(up.radiance, Vec3::from_float(4.));
+    }
+
+    #[test]
+    fn glass_lets_all_light_through() {
+        // Glass spheres inside each other, all the light that enters 
+        // eventually leaves, however many times it bounces inside
+        let glass: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::Dielectric::new(1.5)));
+        let objects: Vec<Box<dyn Hittable + Send + Sync>> = (1..=4)
+            .map(|i| Box::new(Sphere::new(Vec3::ZERO, i as f64 * 0.5, glass.clone())) as Box<dyn Hittable + Send + Sync>)
+            .collect();
+        let world = World {
+            lights: LightList::from_objects(&objects), 
+            objects: Box::new(objects), 
+            background: Box::new(SolidColor::new(Vec3::ONE))
+        };
+
+        let settings = RenderSettings::default();
+        let n = 20000;
+        let mut sampler = Independent::new(1);
+        let total = (0..n).fold(0., |total, i| {
+            sampler.start_sample(0, i);
+            let ray = Ray::new(Vec3::new(0.3 + i as f64 * 1e-4, 0.2, 5.), Vec3::new(0., 0., -1.));
+            total + ray_color(ray, &world, &settings, &mut sampler).radiance.x
+        });
+        assert!((total / n as f64 - 1.).abs() < 0.01, "{}", total / n as f64);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::vec3::Vec3;
+use super::hittable::{Hittable, HitRecord};
+
+
+/*
+    The objects of the world that emit light, kept aside from the BVH so
+    that diffuse surfaces can aim shadow rays straight at them
+*/
+pub struct LightList {
+    lights: Vec<Box<dyn Hittable + Send + Sync>>
+}
+
+impl LightList {
+    pub fn new(lights: Vec<Box<dyn Hittable + Send + Sync>>) -> LightList {
+        LightList {lights}
+    }
+
+    /*
+        Copies the objects of 'objects' whose material emits light
+    */
+    pub fn from_objects(objects: &[Box<dyn Hittable + Send + Sync>]) -> LightList {
+        LightList::new(objects.iter().filter_map(|object| object.as_light()).collect())
+    }
+
+    pub fn len(&self) -> usize { self.lights.len() }
+    pub fn is_empty(&self) -> bool { self.lights.is_empty() }
+
+    /*
+        Picks one of the lights with 'u_light' and a point on it that can
+        be seen from 'origin' with 'u'. The density of the direction
+        accounts for the choice of the light, each one is as likely
+    */
+    pub fn sample(&self, origin: &Vec3, u_light: f64, u: (f64, f64)) -> Option<(HitRecord, f64)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let index = ((u_light * self.len() as f64) as usize).min(self.len() - 1);
+        let (hit_record, pdf) = self.lights[index].sample(origin, u)?;
+        Some((hit_record, pdf / self.len() as f64))
+    }
+
+    /*
+        The density of sample picking 'direction' from 'origin'. This goes 
+        through every light, which is fine as long as there are a few
+    */
+    pub fn pdf(&self, origin: &Vec3, direction: &Vec3) -> f64 {
+        let total: f64 = self.lights.iter().map(|light| light.pdf(origin, direction)).sum();
+        total / self.len().max(1) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::{
+        Material, 
+        material, 
+        hittable::{Sphere, Triangle}
+    };
+    use std::{
+        f64::consts::PI, 
+        sync::Arc
+    };
+
+    fn light() -> Arc<Box<dyn Material + Send + Sync>> {
+        Arc::new(Box::new(material::DiffuseLight::new(Vec3::ONE)))
+    }
+
+    /*
+        Averaging 1 / pdf over many samples estimates the solid angle the
+        object covers as seen from 'origin'
+    */
+    fn solid_angle(object: &dyn Hittable, origin: &Vec3) -> f64 {
+        let n = 200;
+        let mut total = 0.;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let (hit_record, pdf) = object.sample(origin, u).unwrap();
+                assert!(hit_record.t > 0.);
+                let direction = hit_record.p - origin;
+                assert!((object.pdf(origin, &direction) / pdf - 1.).abs() < 1e-6);
+                total += 1. / pdf;
+            }
+        }
+        total / (n * n) as f64
+    }
+
+    #[test]
+    fn sphere_samples_cover_its_solid_angle() {
+        let sphere = Sphere::new(Vec3::new(0., 0., -4.), 2., light());
+        // A cone with an half angle of 30 degrees
+        let expected = 2. * PI * (1. - (PI / 6.).cos());
+        assert!((solid_angle(&sphere, &Vec3::ZERO) - expected).abs() < 1e-9);
+
+        // From inside the sphere is all around
+        assert!((solid_angle(&sphere, &Vec3::new(0.5, 0., -4.)) - 4. * PI).abs() < 0.05);
+    }
+
+    #[test]
+    fn triangle_samples_cover_its_solid_angle() {
+        // One of the eight triangles of an octahedron around the origin
+        let triangle = Triangle::new(Vec3::new(1., 0., 0.), Vec3::new(0., 1., 0.), Vec3::new(0., 0., 1.), light());
+        assert!((solid_angle(&triangle, &Vec3::ZERO) - 4. * PI / 8.).abs() < 0.01);
+    }
+
+    #[test]
+    fn only_emissive_objects_are_lights() {
+        let diffuse: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::Lambertian::new(Vec3::ONE)));
+        let objects: Vec<Box<dyn Hittable + Send + Sync>> = vec![
+            Box::new(Sphere::new(Vec3::ZERO, 1., diffuse)), 
+            Box::new(Sphere::new(Vec3::new(0., 5., 0.), 1., light()))
+        ];
+
+        let lights = LightList::from_objects(&objects);
+        assert_eq!(lights.len(), 1);
+        let (hit_record, _) = lights.sample(&Vec3::ZERO, 0.3, (0.5, 0.5)).unwrap();
+        assert!(hit_record.material.emits_light());
+    }
+}
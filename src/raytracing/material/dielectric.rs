@@ -3,7 +3,8 @@ use crate::raytracing::{
     material::Material,
     material, 
     ray::Ray,  
-    hittable::HitRecord
+    hittable::HitRecord, 
+    Sampler
 };
 
 pub struct Dielectric {
@@ -14,6 +15,8 @@ impl Dielectric {
     pub fn new(refractive_index: f64) -> Dielectric {
         Dielectric {refractive_index}
     }
+
+    pub fn refractive_index(&self) -> f64 { self.refractive_index }
 }
 
 fn reflectance(cosine: f64, refraction_ratio: f64) -> f64 {
@@ -23,7 +26,7 @@ fn reflectance(cosine: f64, refraction_ratio: f64) -> f64 {
 
 impl Material for Dielectric {
     
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Vec3, Ray)> {
         let attenuation = Vec3::ONE;
         let refraction_ratio = {
             if hit_record.front_face { 1.0 / self.refractive_index } else { self.refractive_index }
@@ -36,7 +39,7 @@ impl Material for Dielectric {
 
         let direction = {
             let cannot_refract = refraction_ratio * sin_theta > 1.0;
-            if cannot_refract || reflectance(cos_theta, refraction_ratio) > rand::random() {
+            if cannot_refract || reflectance(cos_theta, refraction_ratio) > sampler.get_1d() {
                 material::reflect(&unit_direction, &hit_record.normal)
             } else {
                 material::refract(&unit_direction, &hit_record.normal, refraction_ratio)
@@ -47,4 +50,9 @@ impl Material for Dielectric {
 
         Some((attenuation, scattered))
     }
+
+    // Glass lets all the light through, whichever way it goes
+    fn base_color(&self, _hit_record: &HitRecord) -> Vec3 {
+        Vec3::ONE
+    }
 }
\ No newline at end of file
@@ -0,0 +1,38 @@
+use crate::vec3::Vec3;
+use crate::raytracing::{
+    material::Material, 
+    ray::Ray,  
+    hittable::HitRecord, 
+    Sampler
+};
+
+
+/*
+    A surface that emits 'emit' light in every direction from both of its 
+    sides and doesn't reflect anything
+*/
+pub struct DiffuseLight {
+    emit: Vec3
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> DiffuseLight {
+        DiffuseLight {emit}
+    }
+
+    pub fn emit(&self) -> &Vec3 { &self.emit }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _ray_in: &Ray, _hit_record: &HitRecord, _sampler: &mut dyn Sampler) -> Option<(Vec3, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _hit_record: &HitRecord) -> Vec3 {
+        self.emit
+    }
+
+    fn emits_light(&self) -> bool {
+        true
+    }
+}
@@ -1,8 +1,10 @@
 use crate::vec3::Vec3;
+use std::f64::consts::PI;
 use crate::raytracing::{
     material::Material, 
     ray::Ray,  
-    hittable::HitRecord
+    hittable::HitRecord, 
+    sampler::{self, Sampler}
 };
 
 
@@ -14,15 +16,23 @@ impl Lambertian {
     pub fn new(albedo: Vec3) -> Lambertian {
         Lambertian {albedo}
     }
+
+    pub fn albedo(&self) -> &Vec3 { &self.albedo }
+
+    fn albedo_at(&self, hit_record: &HitRecord) -> Vec3 {
+        match hit_record.color {
+            Some(color) => self.albedo * color, 
+            None => self.albedo
+        }
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
-        
-        let attenuation = self.albedo;
+    fn scatter(&self, _ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Vec3, Ray)> {
+        let attenuation = self.albedo_at(hit_record);
 
         let scatter_direction = {
-            let dir = hit_record.normal + Vec3::random_unit_vector();
+            let dir = hit_record.normal + sampler::uniform_sphere(sampler.get_2d());
             if !dir.near_zero() { 
                 dir
             } else {
@@ -33,4 +43,21 @@ impl Material for Lambertian {
 
         Some((attenuation, scattered_ray))
     }
+
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Vec3 {
+        self.albedo_at(hit_record) * self.pdf(ray_in, hit_record, direction)
+    }
+
+    /*
+        Adding a random unit vector to the normal picks directions with 
+        a density proportional to their cosine with the normal
+    */
+    fn pdf(&self, _ray_in: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        let cosine = Vec3::dot(&hit_record.normal, &direction.unit_vector());
+        cosine.max(0.) / PI
+    }
+
+    fn base_color(&self, hit_record: &HitRecord) -> Vec3 {
+        self.albedo_at(hit_record)
+    }
 }
\ No newline at end of file
@@ -1,9 +1,11 @@
 use crate::vec3::Vec3;
+use std::f64::consts::PI;
 use crate::raytracing::{
     material::Material,
     material, 
     ray::Ray,  
-    hittable::HitRecord
+    hittable::HitRecord, 
+    sampler::{self, Sampler}
 };
 
 
@@ -21,18 +23,94 @@ impl Metal {
     pub fn new(albedo: Vec3, fuzziness: f64) -> Metal {
         Metal {albedo, fuzziness: fuzziness.min(1.)}
     }
+
+    pub fn albedo(&self) -> &Vec3 { &self.albedo }
+    pub fn fuzziness(&self) -> f64 { self.fuzziness }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)> {
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Vec3, Ray)> {
         let reflected = material::reflect(&ray_in.direction().unit_vector(), &hit_record.normal);
-        let scattered = Ray::new(hit_record.p, reflected + self.fuzziness * Vec3::random_in_unit_sphere());
+        let scattered = Ray::new(hit_record.p, reflected + self.fuzziness * sampler::uniform_ball(sampler.get_2d(), sampler.get_1d()));
         let attenuation = self.albedo;
 
+        #[allow(clippy::needless_borrow)]
         if Vec3::dot(&scattered.direction(), &hit_record.normal) > 0. {
             Some((attenuation, scattered))
         } else {
             None
         }
     }
+
+    /*
+        Scatter only keeps the directions above the surface, and for them 
+        the attenuation is always the albedo
+    */
+    fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: &Vec3) -> Vec3 {
+        match Vec3::dot(direction, &hit_record.normal) > 0. {
+            true => self.albedo * self.pdf(ray_in, hit_record, direction), 
+            false => Vec3::ZERO
+        }
+    }
+
+    fn base_color(&self, _hit_record: &HitRecord) -> Vec3 {
+        self.albedo
+    }
+
+    /*
+        Scattered directions end in a ball of radius 'fuzziness' around the 
+        tip of the reflected direction. The density of a direction is the 
+        share of the ball its half-line goes through, weighted by the 
+        squared distance from the hit point: integrating t^2 along the chord 
+        from t1 to t2 gives (t2^3 - t1^3) / 3, divided by the volume of the ball
+    */
+    fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: &Vec3) -> f64 {
+        if self.fuzziness <= 0. {
+            return 0.;
+        }
+
+        let reflected = material::reflect(&ray_in.direction().unit_vector(), &hit_record.normal);
+        let direction = direction.unit_vector();
+        let b = Vec3::dot(&direction, &reflected);
+        let c = reflected.length_squared() - self.fuzziness * self.fuzziness;
+        let discriminant = b * b - c;
+        if discriminant <= 0. {
+            return 0.;
+        }
+
+        let t1 = (b - discriminant.sqrt()).max(0.);
+        let t2 = b + discriminant.sqrt();
+        if t2 <= 0. {
+            return 0.;
+        }
+        (t2.powi(3) - t1.powi(3)) / (4. * PI * self.fuzziness.powi(3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raytracing::Material;
+    use std::sync::Arc;
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let metal = Metal::new(Vec3::ONE, 0.5);
+        let material: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(Metal::new(Vec3::ONE, 0.5)));
+        let ray_in = Ray::new(Vec3::new(-1., 1., 0.), Vec3::new(1., -1., 0.));
+        let hit_record = HitRecord::new(material, &ray_in, 1., Vec3::new(0., 1., 0.));
+
+        // Midpoint rule over the whole sphere of directions
+        let n = 400;
+        let mut total = 0.;
+        for i in 0..n {
+            for j in 0..2 * n {
+                let theta = PI * (i as f64 + 0.5) / n as f64;
+                let phi = PI * (j as f64 + 0.5) / n as f64;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                total += metal.pdf(&ray_in, &hit_record, &direction) * theta.sin() * (PI / n as f64).powi(2);
+            }
+        }
+        assert!((total - 1.).abs() < 1e-3, "{}", total);
+    }
 }
\ No newline at end of file
@@ -1,25 +1,79 @@
 mod lambertian;
 mod metal;
 mod dielectric;
+mod diffuse_light;
 
 use crate::vec3::Vec3;
 use crate::raytracing::{
     hittable::HitRecord, 
-    ray::Ray
+    ray::Ray, 
+    AsAny, 
+    Sampler
 };
 
 pub use {
     lambertian::Lambertian, 
     metal::Metal, 
-    dielectric::Dielectric
+    dielectric::Dielectric, 
+    diffuse_light::DiffuseLight
 };
 
 
-pub trait Material {
-    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Vec3, Ray)>;
+pub trait Material: AsAny {
+    /*
+        Picks the direction the light arriving at the hit point came from 
+        with the numbers of 'sampler', with how much of it is sent back 
+        along 'ray_in'
+    */
+    fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Option<(Vec3, Ray)>;
+
+    /*
+        The light given off by the surface at the hit point, 
+        most materials don't emit any
+    */
+    fn emitted(&self, _hit_record: &HitRecord) -> Vec3 {
+        Vec3::ZERO
+    }
+
+    /*
+        How much of the light arriving from 'direction' the surface sends 
+        back along the incoming ray, already multiplied by the cosine between 
+        'direction' and the normal. For directions picked by scatter this 
+        divided by their pdf is the attenuation
+    */
+    fn eval(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: &Vec3) -> Vec3 {
+        Vec3::ZERO
+    }
+
+    /*
+        The probability density of scatter picking 'direction', over the 
+        sphere of directions. Materials like glass and perfect mirrors that 
+        only send light in exact directions leave it to zero, lights can't 
+        be aimed at from their surface
+    */
+    fn pdf(&self, _ray_in: &Ray, _hit_record: &HitRecord, _direction: &Vec3) -> f64 {
+        0.
+    }
+
+    /*
+        The color of the surface at the hit point, saved in the albedo 
+        layer. Black for materials that don't reflect light
+    */
+    fn base_color(&self, _hit_record: &HitRecord) -> Vec3 {
+        Vec3::ZERO
+    }
+
+    /*
+        Objects made of materials that emit light are sampled directly 
+        from diffuse surfaces
+    */
+    fn emits_light(&self) -> bool {
+        false
+    }
 }
 
 fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
+    #[allow(clippy::needless_borrow)]
     let cos_theta = Vec3::dot(&-uv, &n).min(1.0);
     let r_out_perpendicular = etai_over_etat * (uv + cos_theta * n);
     let r_out_parallel = n * -((1.0 - r_out_perpendicular.length_squared()).abs()).sqrt();
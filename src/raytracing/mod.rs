@@ -1,84 +1,202 @@
-use crate::{
-    vec3::Vec3, 
-    image_formats::Image
-};
 use rayon::prelude::*;
 
 
 mod ray;
+mod integrator;
+mod distribution;
 mod camera;
 pub mod hittable;
 pub mod material;
+pub mod background;
+pub mod lights;
+pub mod sampler;
+pub mod adaptive;
+pub mod film;
 
 pub use {
     camera::Camera, 
     material::Material, 
     hittable::Hittable, 
+    background::Background, 
+    lights::LightList, 
+    sampler::{Sampler, SamplerKind}, 
+    adaptive::AdaptiveSampling, 
+    film::Film, 
+    integrator::PathSample, 
     ray::Ray
 };
-use std::sync::Arc;
+use std::any::Any;
+
+
+/*
+    Lets code holding a Hittable or a Material trait object find out which 
+    concrete type is behind it, for example to save a world to a scene file. 
+    Call it on the trait object itself, not on the Box or Arc around it, or 
+    you will get back the Box or the Arc
+*/
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+}
 
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any { self }
+}
 
-fn clamp(min: f64, x: f64, max: f64) -> f64 {
-    x.min(max).max(min)
+/*
+    Everything rays can find: the objects, usually inside a BVH, the 
+    background and the objects that emit light among them
+*/
+pub struct World {
+    pub objects: Box<dyn Hittable + Send + Sync>, 
+    pub background: Box<dyn Background + Send + Sync>, 
+    pub lights: LightList
 }
 
-fn gamma2_color_correction(color: &Vec3) -> Vec3 {
-    Vec3::new(
-        color.x.sqrt(), 
-        color.y.sqrt(), 
-        color.z.sqrt()
-    )
+/*
+    How far paths are followed. After 'roulette_depth' bounces paths carrying 
+    little light are randomly stopped, and the ones that go on carry more 
+    to make up for it, so the image stays the same on average. 'max_depth' 
+    is a hard limit on the number of bounces, only reached by paths that 
+    keep carrying a lot of light like ones trapped between mirrors. The 
+    same 'seed' always gives the same image, 'sampler' picks how the random 
+    numbers of the samples of a pixel are spread over the 'samples_per_pixel' 
+    samples the whole render takes, however many passes it is split in. 
+    With 'adaptive' pixels stop taking samples once they look good enough
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    pub max_depth: u32, 
+    pub roulette_depth: u32, 
+    pub seed: u64, 
+    pub sampler: SamplerKind, 
+    pub samples_per_pixel: u32, 
+    pub adaptive: Option<AdaptiveSampling>
 }
 
-pub fn draw_world_with_callback<F>(
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {max_depth: 100, roulette_depth: 3, seed: 0, sampler: SamplerKind::Sobol, samples_per_pixel: 50, adaptive: None}
+    }
+}
+
+/*
+    Adds up to 'samples' samples to every pixel of 'film', fewer for the 
+    pixels adaptive sampling finds good enough. Samples are numbered from 
+    the ones already in the film, so rendering in many passes gives the 
+    same image as rendering all the samples at once. 'on_row_render' is 
+    called after each row
+*/
+pub fn render_pass<F>(
     camera: &Camera, 
-    world: &Vec<Box<dyn Hittable + Send + Sync>>, 
-    image: &mut impl Image, 
-    samples_per_pixel: i32, 
-    on_row_render: F
+    world: &World, 
+    settings: &RenderSettings, 
+    film: &mut Film, 
+    samples: u32, 
+    on_row_render: &F
 ) 
 where 
-    F: Fn() -> () + Send + Sync + 'static
+    F: Fn() + Sync
 {
-    const MAX_DEPTH: i32 = 10;
-    let scale = 1.0 / samples_per_pixel as f64;
-    
-    let image_width = image.width();
-    let image_height = image.height();
-
-    let on_row_render = Arc::new(on_row_render);
-
-    let pixels = (0..image.height())
-        .into_par_iter()
-        .map_with(
-            on_row_render.clone(), 
-            |on_row_render, y| {
-                let row = (0..image_width).into_par_iter().map(|x| {
+    let image_width = film.width();
+    let image_height = film.height();
+
+    film.pixels_mut()
+        .par_chunks_mut(image_width)
+        .enumerate()
+        .for_each(|(y, row)| {
+            row.par_iter_mut().enumerate().for_each(|(x, film_pixel)| {
                 let j = image_height - y;
+                let pixel = (y * image_width + x) as u64;
+                let mut sampler = sampler::new(settings.sampler, settings.seed, settings.samples_per_pixel as u64);
 
-                let mut color = (0..samples_per_pixel).into_par_iter().map(|_| {
-                    let u = (x as f64 + rand::random::<f64>()) / (image_width - 1) as f64;
-                    let v = (j as f64 + rand::random::<f64>()) / (image_height - 1) as f64;
-                    let r = camera.get_ray(u, v);
+                // Added in order, adding floats in a different order can change the result
+                for _ in 0..samples {
+                    if settings.adaptive.is_some_and(|adaptive| adaptive.converged(&film_pixel.stats)) {
+                        break;
+                    }
 
-                    r.ray_color(world, MAX_DEPTH)
-                }).reduce(|| Vec3::ZERO, |a, b| a + b);
+                    sampler.start_sample(pixel, film_pixel.stats.count() as u64);
+                    let (du, dv) = sampler.get_2d();
+                    let u = (x as f64 + du) / (image_width - 1) as f64;
+                    let v = (j as f64 + dv) / (image_height - 1) as f64;
+                    let r = camera.get_ray(u, v, sampler.get_2d());
+
+                    film_pixel.add(&integrator::ray_color(r, world, settings, sampler.as_mut()));
+                }
+            });
+            on_row_render();
+        });
+}
 
-                color.x = clamp(0., color.x * scale, 0.9999);
-                color.y = clamp(0., color.y * scale, 0.9999);
-                color.z = clamp(0., color.z * scale, 0.9999);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        vec3::Vec3, 
+        image_formats::FloatImage
+    };
+    use std::sync::Arc;
 
-                gamma2_color_correction(&color).to_color()
-            }
-        ).collect::<Vec<_>>();
-        on_row_render();
-        row
-    }).collect::<Vec<_>>();
+    /*
+        Renders a small scene in passes of 'passes' samples per pixel, 
+        returns the png file and the samples each pixel took
+    */
+    fn render(settings: &RenderSettings, threads: usize, passes: &[u32]) -> (FloatImage, Vec<u32>) {
+        let diffuse: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::Lambertian::new(Vec3::new(0.8, 0.5, 0.2))));
+        let metal: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::Metal::new(Vec3::from_float(0.9), 0.3)));
+        let glass: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::Dielectric::new(1.5)));
+        let light: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::DiffuseLight::new(Vec3::from_float(4.))));
+        let objects: Vec<Box<dyn Hittable + Send + Sync>> = vec![
+            Box::new(hittable::Sphere::new(Vec3::new(0., -100.5, -1.), 100., diffuse)), 
+            Box::new(hittable::Sphere::new(Vec3::new(-1., 0., -1.), 0.5, metal)), 
+            Box::new(hittable::Sphere::new(Vec3::new(1., 0., -1.), 0.5, glass)), 
+            Box::new(hittable::Sphere::new(Vec3::new(0., 2., -1.), 0.5, light))
+        ];
+        let world = World {
+            lights: LightList::from_objects(&objects), 
+            objects: Box::new(objects), 
+            background: Box::new(background::Gradient::new(Vec3::ONE, Vec3::new(0.5, 0.7, 1.)))
+        };
+        // An aperture so that the lens is sampled too
+        let camera = Camera::new(&Vec3::new(0., 0.5, 2.), &Vec3::new(0., 0., -1.), &Vec3::new(0., 1., 0.), 60., 4. / 3., 0.2, 3.);
 
-    for (y, row_pixels) in pixels.iter().enumerate() {
-        for (x, color) in row_pixels.iter().enumerate() {
-            image.putpixel(x, y, *color);
+        let mut film = Film::new(16, 12);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        for samples in passes {
+            pool.install(|| render_pass(&camera, &world, settings, &mut film, *samples, &|| {}));
+        }
+
+        (film.image(), film.sample_counts())
+    }
+
+    #[test]
+    fn same_seed_same_image() {
+        let settings = |seed| RenderSettings {seed, sampler: SamplerKind::Independent, ..RenderSettings::default()};
+        assert_eq!(render(&settings(7), 1, &[8]), render(&settings(7), 4, &[8]));
+        assert_ne!(render(&settings(7), 4, &[8]).0, render(&settings(8), 4, &[8]).0);
+    }
+
+    #[test]
+    fn passes_add_up_to_the_whole_render() {
+        for sampler in SamplerKind::ALL.iter() {
+            let settings = RenderSettings {sampler: *sampler, samples_per_pixel: 8, ..RenderSettings::default()};
+            assert_eq!(render(&settings, 4, &[2, 3, 3]), render(&settings, 4, &[8]), "{}", sampler);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn noisy_pixels_take_more_samples() {
+        let settings = RenderSettings {
+            adaptive: Some(AdaptiveSampling {min_samples: 4, threshold: 0.02}), 
+            ..RenderSettings::default()
+        };
+        let (_, counts) = render(&settings, 4, &[64]);
+        // The top left corner only sees the sky, the bottom row sees the ground
+        assert_eq!(counts[0], 4);
+        assert!(counts[16 * 11..].iter().any(|count| *count > 4));
+        assert!(counts.iter().all(|count| (4..=64).contains(count)));
+
+        let (_, counts) = render(&RenderSettings::default(), 4, &[8]);
+        assert!(counts.iter().all(|count| *count == 8));
+    }
+}
@@ -0,0 +1,75 @@
+use super::{Sampler, hash, permutation_element, to_unit};
+
+
+// One base for each dimension, the numbers after them are random
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131
+];
+
+/*
+    The Halton sequence: sample i takes the digits of i in a different 
+    prime base for each dimension, mirrored around the decimal point. The 
+    digits are shuffled with Owen scrambling, differently for each pixel 
+    and dimension, so that pixels next to each other don't look alike and 
+    the bigger bases don't line up with each other
+*/
+pub struct Halton {
+    seed: u64, 
+    pixel: u64, 
+    index: u64, 
+    dimension: usize
+}
+
+impl Halton {
+    pub fn new(seed: u64) -> Halton {
+        Halton {seed, pixel: 0, index: 0, dimension: 0}
+    }
+
+    fn next(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        let key = hash(&[self.seed, self.pixel, dimension as u64]);
+        match PRIMES.get(dimension) {
+            Some(base) => owen_scrambled_radical_inverse(self.index, *base, key), 
+            None => to_unit(hash(&[key, self.index]))
+        }
+    }
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+/*
+    The digits of 'i' in base 'base' written in reverse after the decimal 
+    point, 0.1, 0.01, 0.11... in base 2, with each digit replaced through a 
+    random permutation that depends on 'key' and the digits before it. 
+    The zeros after the last digit of 'i' are shuffled too, until they are 
+    too small to matter
+*/
+fn owen_scrambled_radical_inverse(mut i: u64, base: u64, key: u64) -> f64 {
+    let inverse_base = 1. / base as f64;
+    let mut scale = 1.;
+    let mut reversed = 0u64;
+    while 1. - scale < 1. {
+        let digit = permutation_element(i % base, base, hash(&[key, reversed]));
+        reversed = reversed * base + digit;
+        scale *= inverse_base;
+        i /= base;
+    }
+    (reversed as f64 * scale).min(1. - f64::EPSILON)
+}
@@ -0,0 +1,32 @@
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use super::{Sampler, hash};
+
+
+/*
+    Plain random numbers, every sample of every pixel has its own generator 
+    so the image doesn't depend on which thread renders what
+*/
+pub struct Independent {
+    seed: u64, 
+    rng: SmallRng
+}
+
+impl Independent {
+    pub fn new(seed: u64) -> Independent {
+        Independent {seed, rng: SmallRng::seed_from_u64(seed)}
+    }
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.rng = SmallRng::seed_from_u64(hash(&[self.seed, pixel, index]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
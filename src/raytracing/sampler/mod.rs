@@ -0,0 +1,255 @@
+mod independent;
+mod stratified;
+mod halton;
+mod sobol;
+
+use crate::vec3::Vec3;
+use std::{
+    f64::consts::PI, 
+    fmt, 
+    str::FromStr
+};
+
+pub use {
+    independent::Independent, 
+    stratified::Stratified, 
+    halton::Halton, 
+    sobol::Sobol
+};
+
+
+/*
+    Gives the numbers in [0, 1) every sample of a pixel is made from: the
+    position inside the pixel, the point on the lens and then a few for
+    each bounce. Samples of the same pixel that take the same dimension
+    can be spread out more evenly than random numbers would be, which gets
+    to the same noise with fewer samples
+*/
+pub trait Sampler {
+    /*
+        Moves to sample 'index' of pixel 'pixel', starting again from the
+        first dimension
+    */
+    fn start_sample(&mut self, pixel: u64, index: u64);
+
+    // The next dimension of the sample
+    fn get_1d(&mut self) -> f64;
+
+    // The next two dimensions of the sample, evenly spread together
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+    Independent, 
+    Stratified, 
+    Halton, 
+    Sobol
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Independent, 
+        SamplerKind::Stratified, 
+        SamplerKind::Halton, 
+        SamplerKind::Sobol
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent", 
+            SamplerKind::Stratified => "stratified", 
+            SamplerKind::Halton => "halton", 
+            SamplerKind::Sobol => "sobol"
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SamplerKind, String> {
+        SamplerKind::ALL.iter().copied().find(|kind| kind.name() == s).ok_or_else(|| {
+            let names: Vec<_> = SamplerKind::ALL.iter().map(|kind| kind.name()).collect();
+            format!("unknown sampler '{}', expected one of {}", s, names.join(", "))
+        })
+    }
+}
+
+/*
+    A sampler of kind 'kind' for pixels that take 'samples_per_pixel'
+    samples, the same 'seed' always gives the same numbers
+*/
+pub fn new(kind: SamplerKind, seed: u64, samples_per_pixel: u64) -> Box<dyn Sampler + Send> {
+    match kind {
+        SamplerKind::Independent => Box::new(Independent::new(seed)), 
+        SamplerKind::Stratified => Box::new(Stratified::new(seed, samples_per_pixel)), 
+        SamplerKind::Halton => Box::new(Halton::new(seed)), 
+        SamplerKind::Sobol => Box::new(Sobol::new(seed))
+    }
+}
+
+/*
+    Scrambles the bits of 'x', from the SplitMix64 generator. Close inputs
+    give unrelated outputs
+*/
+fn mix(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |h, v| mix(h ^ v))
+}
+
+// The 53 top bits of 'bits' as a number in [0, 1)
+fn to_unit(bits: u64) -> f64 {
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/*
+    Element 'i' of a random permutation of 0..'count' picked by 'key', 
+    without building the permutation. From "Correlated Multi-Jittered 
+    Sampling" by Andrew Kensler
+*/
+fn permutation_element(i: u64, count: u64, key: u64) -> u64 {
+    let (count, p) = (count as u32, key as u32);
+    let w = count.wrapping_sub(1);
+    let w = w | w >> 1;
+    let w = w | w >> 2;
+    let w = w | w >> 4;
+    let w = w | w >> 8;
+    let w = w | w >> 16;
+
+    let mut i = i as u32;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        // Values past the end are skipped by going on until one is inside
+        if i < count {
+            break;
+        }
+    }
+    (i as u64 + p as u64) % count as u64
+}
+
+/*
+    A direction picked uniformly over the sphere
+*/
+pub fn uniform_sphere(u: (f64, f64)) -> Vec3 {
+    let z = 1. - 2. * u.0;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/*
+    A point picked uniformly inside the ball of radius 1: a direction and
+    a distance from the center, which is more likely to be far since there
+    is more room there
+*/
+pub fn uniform_ball(u: (f64, f64), u_radius: f64) -> Vec3 {
+    uniform_sphere(u) * u_radius.cbrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+        The average of x * y over the samples of a pixel, which should be
+        close to its integral 1/4
+    */
+    fn integrate(kind: SamplerKind, pixel: u64, n: u64) -> f64 {
+        let mut sampler = new(kind, 3, n);
+        let total: f64 = (0..n).map(|i| {
+            sampler.start_sample(pixel, i);
+            // A few dimensions in, like the ones of a bounce
+            sampler.get_2d();
+            sampler.get_1d();
+            let (x, y) = sampler.get_2d();
+            assert!((0. ..1.).contains(&x) && (0. ..1.).contains(&y));
+            x * y
+        }).sum();
+        total / n as f64
+    }
+
+    #[test]
+    fn even_samplers_beat_random_numbers() {
+        for kind in SamplerKind::ALL.iter() {
+            let pixels = 32;
+            let error = (0..pixels).map(|pixel| (integrate(*kind, pixel, 64) - 0.25).powi(2)).sum::<f64>() / pixels as f64;
+            // Random numbers have an error of about 0.02
+            let limit = if *kind == SamplerKind::Independent { 0.03 } else { 0.01 };
+            assert!(error.sqrt() < limit, "{}: {}", kind, error.sqrt());
+        }
+    }
+
+    #[test]
+    fn samplers_cover_every_stratum() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol].iter() {
+            let mut sampler = new(*kind, 5, 16);
+            let mut cells = [0; 16];
+            let mut strata = [0; 16];
+            for i in 0..16 {
+                sampler.start_sample(7, i);
+                let (x, y) = sampler.get_2d();
+                cells[(y * 4.) as usize * 4 + (x * 4.) as usize] += 1;
+                strata[(sampler.get_1d() * 16.) as usize] += 1;
+            }
+            assert_eq!(cells, [1; 16], "{}", kind);
+            assert_eq!(strata, [1; 16], "{}", kind);
+        }
+    }
+
+    #[test]
+    fn same_sample_same_numbers() {
+        for kind in SamplerKind::ALL.iter() {
+            let mut sampler = new(*kind, 1, 16);
+            let mut numbers = || {
+                sampler.start_sample(3, 5);
+                (sampler.get_1d(), sampler.get_2d())
+            };
+            assert_eq!(numbers(), numbers(), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn parse_kinds() {
+        assert_eq!("halton".parse(), Ok(SamplerKind::Halton));
+        assert!("quasi".parse::<SamplerKind>().unwrap_err().contains("independent, stratified, halton, sobol"));
+    }
+
+    #[test]
+    fn points_in_the_ball() {
+        for i in 0..100 {
+            let u = ((i as f64 * 0.618) % 1., (i as f64 * 0.382) % 1.);
+            assert!((uniform_sphere(u).length() - 1.).abs() < 1e-12);
+            assert!(uniform_ball(u, (i as f64 + 0.5) / 100.).length() < 1.);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use super::{Sampler, hash};
+
+
+/*
+    The directions of the second dimension of the Sobol sequence, the first 
+    one just mirrors the bits of the index
+*/
+const DIRECTIONS: [u32; 32] = {
+    let mut directions = [0; 32];
+    directions[0] = 1 << 31;
+    let mut i = 1;
+    while i < 32 {
+        directions[i] = directions[i - 1] ^ (directions[i - 1] >> 1);
+        i += 1;
+    }
+    directions
+};
+
+/*
+    The first two dimensions of the Sobol sequence, with Owen scrambling: 
+    each pair of dimensions of each pixel shuffles the order of the points 
+    and randomly swaps halves, quarters and so on of the square they are 
+    in, which keeps them as evenly spread. From "Practical Hash-based Owen 
+    Scrambling" by Brent Burley
+*/
+pub struct Sobol {
+    seed: u64, 
+    pixel: u64, 
+    index: u32, 
+    dimension: u64
+}
+
+impl Sobol {
+    pub fn new(seed: u64) -> Sobol {
+        Sobol {seed, pixel: 0, index: 0, dimension: 0}
+    }
+
+    fn next_key(&mut self) -> u32 {
+        self.dimension += 1;
+        hash(&[self.seed, self.pixel, self.dimension]) as u32
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let key = self.next_key();
+        let index = nested_uniform_scramble(self.index, key);
+        to_unit(nested_uniform_scramble(index.reverse_bits(), key ^ 0x5851_f42d))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let key = self.next_key();
+        let index = nested_uniform_scramble(self.index, key);
+        let x = index.reverse_bits();
+        let y = (0..32)
+            .filter(|bit| index >> bit & 1 == 1)
+            .fold(0, |y, bit| y ^ DIRECTIONS[bit]);
+        (
+            to_unit(nested_uniform_scramble(x, key ^ 0x5851_f42d)), 
+            to_unit(nested_uniform_scramble(y, key ^ 0x4c95_7f2d))
+        )
+    }
+}
+
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 / (1u64 << 32) as f64
+}
+
+/*
+    A random permutation of the bits of 'x' in which each bit only depends 
+    on the ones below it
+*/
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+/*
+    Owen scrambling of the number 0.x in binary: each bit is flipped 
+    depending on the ones above it
+*/
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
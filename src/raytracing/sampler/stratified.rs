@@ -0,0 +1,60 @@
+use super::{Sampler, hash, permutation_element, to_unit};
+
+
+/*
+    Splits each dimension in as many strata as there are samples per pixel, 
+    or a grid of about as many cells for two dimensions, and gives each 
+    sample a random point in a different one. Which sample gets which 
+    stratum is shuffled again for every dimension
+*/
+pub struct Stratified {
+    seed: u64, 
+    samples_per_pixel: u64, 
+    pixel: u64, 
+    index: u64, 
+    dimension: u64
+}
+
+impl Stratified {
+    pub fn new(seed: u64, samples_per_pixel: u64) -> Stratified {
+        Stratified {seed, samples_per_pixel: samples_per_pixel.max(1), pixel: 0, index: 0, dimension: 0}
+    }
+
+    /*
+        The stratum out of 'count' of the current sample, and the random 
+        numbers for the point inside it
+    */
+    fn next_stratum(&mut self, count: u64) -> (u64, u64) {
+        let key = hash(&[self.seed, self.pixel, self.dimension]);
+        self.dimension += 1;
+        let stratum = permutation_element(self.index % count, count, key);
+        (stratum, hash(&[key, self.index]))
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, pixel: u64, index: u64) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let n = self.samples_per_pixel;
+        let (stratum, jitter) = self.next_stratum(n);
+        (stratum as f64 + to_unit(jitter)) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        // Enough columns and rows for every sample to have its own cell
+        let columns = (self.samples_per_pixel as f64).sqrt().ceil() as u64;
+        let rows = self.samples_per_pixel.div_ceil(columns);
+
+        let (cell, jitter) = self.next_stratum(columns * rows);
+        let jitter_y = hash(&[jitter]);
+        (
+            ((cell % columns) as f64 + to_unit(jitter)) / columns as f64, 
+            ((cell / columns) as f64 + to_unit(jitter_y)) / rows as f64
+        )
+    }
+}
@@ -0,0 +1,482 @@
+use serde::{Serialize, Deserialize};
+use std::{
+    collections::BTreeMap, 
+    convert::TryFrom
+};
+
+
+/*
+    These types mirror the layout of a scene file one to one, they are 
+    deserialized first and then validated and turned into the actual 
+    camera, materials and objects used by the renderer
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SceneDescription {
+    pub camera: CameraDescription, 
+    // The sky gradient when missing
+    #[serde(default)]
+    pub background: BackgroundDescription, 
+    #[serde(default)]
+    pub render: RenderDescription, 
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>, 
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub look_from: [f64; 3], 
+    pub look_at: [f64; 3], 
+    #[serde(default = "default_vup")]
+    pub vup: [f64; 3], 
+    pub vertical_fov: f64, 
+    #[serde(default)]
+    pub aperture: f64, 
+    // When missing the camera focuses on the look_at point
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_distance: Option<f64>
+}
+
+pub(super) fn default_vup() -> [f64; 3] { [0., 1., 0.] }
+
+/*
+    How the scene should be rendered, the renderer picks a default for 
+    anything missing
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RenderDescription {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<u32>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roulette_depth: Option<u32>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawBackground", into = "RawBackground")]
+pub enum BackgroundDescription {
+    Black, 
+    Color { color: [f64; 3] }, 
+    Gradient { bottom: [f64; 3], top: [f64; 3] }, 
+    // An image in latitude-longitude layout wrapped around the scene
+    Image {
+        path: String, 
+        // Degrees around the vertical axis
+        rotation: f64, 
+        // Multiplies the colors of the image
+        intensity: f64
+    }
+}
+
+impl Default for BackgroundDescription {
+    fn default() -> BackgroundDescription {
+        BackgroundDescription::Gradient { bottom: SKY_BOTTOM, top: SKY_TOP }
+    }
+}
+
+pub(super) const SKY_BOTTOM: [f64; 3] = [1., 1., 1.];
+pub(super) const SKY_TOP: [f64; 3] = [0.5, 0.7, 1.];
+
+/*
+    Materials and objects are tagged with a "type" field. Serde's internally 
+    tagged enums buffer their content before picking the variant, which 
+    loses track of which field failed to parse, so they go through a flat 
+    struct with every possible field instead and get checked afterwards
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawMaterial", into = "RawMaterial")]
+pub enum MaterialDescription {
+    Lambertian { albedo: [f64; 3] }, 
+    Metal { albedo: [f64; 3], fuzziness: f64 }, 
+    Dielectric { refractive_index: f64 }, 
+    DiffuseLight { emit: [f64; 3] }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "RawObject", into = "RawObject")]
+pub enum ObjectDescription {
+    Sphere { center: [f64; 3], radius: f64, material: String }, 
+    Triangle { vertices: [[f64; 3]; 3], material: String }, 
+    Mesh {
+        positions: Vec<[f64; 3]>, 
+        // Each triangle is made of three indices in the list of positions
+        triangles: Vec<[usize; 3]>, 
+        normals: Option<Vec<[f64; 3]>>, 
+        uvs: Option<Vec<[f64; 2]>>, 
+        colors: Option<Vec<[f64; 3]>>, 
+        // Defaults to true when the mesh has normals
+        smooth_shading: Option<bool>, 
+        material: String
+    }, 
+    // A model loaded from an .obj file
+    Model {
+        // Relative to the folder of the scene file
+        path: String, 
+        // Replaces the materials of the model when set
+        material: Option<String>, 
+        scale: f64, 
+        translate: [f64; 3]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum BackgroundType {
+    Black, 
+    Color, 
+    Gradient, 
+    Image
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBackground {
+    #[serde(rename = "type")]
+    kind: BackgroundType, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color: Option<[f64; 3]>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bottom: Option<[f64; 3]>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    top: Option<[f64; 3]>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rotation: Option<f64>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    intensity: Option<f64>
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum MaterialType {
+    Lambertian, 
+    Metal, 
+    Dielectric, 
+    DiffuseLight
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMaterial {
+    #[serde(rename = "type")]
+    kind: MaterialType, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    albedo: Option<[f64; 3]>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fuzziness: Option<f64>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refractive_index: Option<f64>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    emit: Option<[f64; 3]>
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ObjectType {
+    Sphere, 
+    Triangle, 
+    Mesh, 
+    Model
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawObject {
+    #[serde(rename = "type")]
+    kind: ObjectType, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    center: Option<[f64; 3]>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    radius: Option<f64>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vertices: Option<[[f64; 3]; 3]>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    positions: Option<Vec<[f64; 3]>>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    triangles: Option<Vec<[usize; 3]>>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    normals: Option<Vec<[f64; 3]>>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uvs: Option<Vec<[f64; 2]>>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    colors: Option<Vec<[f64; 3]>>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    smooth_shading: Option<bool>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scale: Option<f64>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    translate: Option<[f64; 3]>, 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    material: Option<String>
+}
+
+fn required<T>(value: Option<T>, field: &str) -> Result<T, String> {
+    value.ok_or_else(|| format!("missing field `{}`", field))
+}
+
+fn unused<T>(value: &Option<T>, field: &str, kind: &str) -> Result<(), String> {
+    match value {
+        Some(_) => Err(format!("field `{}` is not used by {}", field, kind)), 
+        None => Ok(())
+    }
+}
+
+impl TryFrom<RawBackground> for BackgroundDescription {
+    type Error = String;
+
+    fn try_from(raw: RawBackground) -> Result<BackgroundDescription, String> {
+        let not_gradient = |kind| -> Result<(), String> {
+            unused(&raw.bottom, "bottom", kind)?;
+            unused(&raw.top, "top", kind)
+        };
+        let not_image = |kind| -> Result<(), String> {
+            unused(&raw.path, "path", kind)?;
+            unused(&raw.rotation, "rotation", kind)?;
+            unused(&raw.intensity, "intensity", kind)
+        };
+
+        match raw.kind {
+            BackgroundType::Black => {
+                not_gradient("black backgrounds")?;
+                unused(&raw.color, "color", "black backgrounds")?;
+                not_image("black backgrounds")?;
+                Ok(BackgroundDescription::Black)
+            }, 
+            BackgroundType::Color => {
+                not_gradient("color backgrounds")?;
+                not_image("color backgrounds")?;
+                Ok(BackgroundDescription::Color { color: required(raw.color, "color")? })
+            }, 
+            BackgroundType::Gradient => {
+                unused(&raw.color, "color", "gradient backgrounds")?;
+                not_image("gradient backgrounds")?;
+                Ok(BackgroundDescription::Gradient {
+                    bottom: raw.bottom.unwrap_or(SKY_BOTTOM), 
+                    top: raw.top.unwrap_or(SKY_TOP)
+                })
+            }, 
+            BackgroundType::Image => {
+                not_gradient("image backgrounds")?;
+                unused(&raw.color, "color", "image backgrounds")?;
+                Ok(BackgroundDescription::Image {
+                    path: required(raw.path, "path")?, 
+                    rotation: raw.rotation.unwrap_or(0.), 
+                    intensity: raw.intensity.unwrap_or(1.)
+                })
+            }
+        }
+    }
+}
+
+impl From<BackgroundDescription> for RawBackground {
+    fn from(description: BackgroundDescription) -> RawBackground {
+        let raw = |kind| RawBackground {
+            kind, 
+            color: None, 
+            bottom: None, 
+            top: None, 
+            path: None, 
+            rotation: None, 
+            intensity: None
+        };
+
+        match description {
+            BackgroundDescription::Black => raw(BackgroundType::Black), 
+            BackgroundDescription::Color { color } => RawBackground { 
+                color: Some(color), ..raw(BackgroundType::Color) 
+            }, 
+            BackgroundDescription::Gradient { bottom, top } => RawBackground { 
+                bottom: Some(bottom), top: Some(top), ..raw(BackgroundType::Gradient) 
+            }, 
+            BackgroundDescription::Image { path, rotation, intensity } => RawBackground { 
+                path: Some(path), 
+                rotation: Some(rotation), 
+                intensity: Some(intensity), 
+                ..raw(BackgroundType::Image) 
+            }
+        }
+    }
+}
+
+impl TryFrom<RawMaterial> for MaterialDescription {
+    type Error = String;
+
+    fn try_from(raw: RawMaterial) -> Result<MaterialDescription, String> {
+        match raw.kind {
+            MaterialType::Lambertian => {
+                unused(&raw.emit, "emit", "lambertian materials")?;
+                unused(&raw.fuzziness, "fuzziness", "lambertian materials")?;
+                unused(&raw.refractive_index, "refractive_index", "lambertian materials")?;
+                Ok(MaterialDescription::Lambertian { albedo: required(raw.albedo, "albedo")? })
+            }, 
+            MaterialType::Metal => {
+                unused(&raw.emit, "emit", "metal materials")?;
+                unused(&raw.refractive_index, "refractive_index", "metal materials")?;
+                Ok(MaterialDescription::Metal {
+                    albedo: required(raw.albedo, "albedo")?, 
+                    fuzziness: raw.fuzziness.unwrap_or(0.)
+                })
+            }, 
+            MaterialType::Dielectric => {
+                unused(&raw.emit, "emit", "dielectric materials")?;
+                unused(&raw.albedo, "albedo", "dielectric materials")?;
+                unused(&raw.fuzziness, "fuzziness", "dielectric materials")?;
+                Ok(MaterialDescription::Dielectric { refractive_index: required(raw.refractive_index, "refractive_index")? })
+            }, 
+            MaterialType::DiffuseLight => {
+                unused(&raw.albedo, "albedo", "diffuse lights")?;
+                unused(&raw.fuzziness, "fuzziness", "diffuse lights")?;
+                unused(&raw.refractive_index, "refractive_index", "diffuse lights")?;
+                Ok(MaterialDescription::DiffuseLight { emit: required(raw.emit, "emit")? })
+            }
+        }
+    }
+}
+
+impl From<MaterialDescription> for RawMaterial {
+    fn from(description: MaterialDescription) -> RawMaterial {
+        let raw = |kind| RawMaterial {kind, albedo: None, fuzziness: None, refractive_index: None, emit: None};
+
+        match description {
+            MaterialDescription::Lambertian { albedo } => RawMaterial { 
+                albedo: Some(albedo), ..raw(MaterialType::Lambertian) 
+            }, 
+            MaterialDescription::Metal { albedo, fuzziness } => RawMaterial { 
+                albedo: Some(albedo), fuzziness: Some(fuzziness), ..raw(MaterialType::Metal) 
+            }, 
+            MaterialDescription::Dielectric { refractive_index } => RawMaterial { 
+                refractive_index: Some(refractive_index), ..raw(MaterialType::Dielectric) 
+            }, 
+            MaterialDescription::DiffuseLight { emit } => RawMaterial { 
+                emit: Some(emit), ..raw(MaterialType::DiffuseLight) 
+            }
+        }
+    }
+}
+
+impl TryFrom<RawObject> for ObjectDescription {
+    type Error = String;
+
+    fn try_from(raw: RawObject) -> Result<ObjectDescription, String> {
+        let not_mesh = |kind| -> Result<(), String> {
+            unused(&raw.positions, "positions", kind)?;
+            unused(&raw.triangles, "triangles", kind)?;
+            unused(&raw.normals, "normals", kind)?;
+            unused(&raw.uvs, "uvs", kind)?;
+            unused(&raw.colors, "colors", kind)?;
+            unused(&raw.smooth_shading, "smooth_shading", kind)
+        };
+        let not_model = |kind| -> Result<(), String> {
+            unused(&raw.path, "path", kind)?;
+            unused(&raw.scale, "scale", kind)?;
+            unused(&raw.translate, "translate", kind)
+        };
+
+        match raw.kind {
+            ObjectType::Sphere => {
+                not_mesh("spheres")?;
+                not_model("spheres")?;
+                unused(&raw.vertices, "vertices", "spheres")?;
+                Ok(ObjectDescription::Sphere {
+                    center: required(raw.center, "center")?, 
+                    radius: required(raw.radius, "radius")?, 
+                    material: required(raw.material, "material")?
+                })
+            }, 
+            ObjectType::Triangle => {
+                not_mesh("triangles")?;
+                not_model("triangles")?;
+                unused(&raw.center, "center", "triangles")?;
+                unused(&raw.radius, "radius", "triangles")?;
+                Ok(ObjectDescription::Triangle {
+                    vertices: required(raw.vertices, "vertices")?, 
+                    material: required(raw.material, "material")?
+                })
+            }, 
+            ObjectType::Mesh => {
+                not_model("meshes")?;
+                unused(&raw.center, "center", "meshes")?;
+                unused(&raw.radius, "radius", "meshes")?;
+                unused(&raw.vertices, "vertices", "meshes")?;
+                Ok(ObjectDescription::Mesh {
+                    positions: required(raw.positions, "positions")?, 
+                    triangles: required(raw.triangles, "triangles")?, 
+                    normals: raw.normals, 
+                    uvs: raw.uvs, 
+                    colors: raw.colors, 
+                    smooth_shading: raw.smooth_shading, 
+                    material: required(raw.material, "material")?
+                })
+            }, 
+            ObjectType::Model => {
+                not_mesh("models")?;
+                unused(&raw.center, "center", "models")?;
+                unused(&raw.radius, "radius", "models")?;
+                unused(&raw.vertices, "vertices", "models")?;
+                Ok(ObjectDescription::Model {
+                    path: required(raw.path, "path")?, 
+                    material: raw.material, 
+                    scale: raw.scale.unwrap_or(1.), 
+                    translate: raw.translate.unwrap_or([0., 0., 0.])
+                })
+            }
+        }
+    }
+}
+
+impl From<ObjectDescription> for RawObject {
+    fn from(description: ObjectDescription) -> RawObject {
+        let raw = |kind, material: Option<String>| RawObject {
+            kind, 
+            center: None, 
+            radius: None, 
+            vertices: None, 
+            positions: None, 
+            triangles: None, 
+            normals: None, 
+            uvs: None, 
+            colors: None, 
+            smooth_shading: None, 
+            path: None, 
+            scale: None, 
+            translate: None, 
+            material
+        };
+
+        match description {
+            ObjectDescription::Sphere { center, radius, material } => RawObject {
+                center: Some(center), 
+                radius: Some(radius), 
+                ..raw(ObjectType::Sphere, Some(material))
+            }, 
+            ObjectDescription::Triangle { vertices, material } => RawObject {
+                vertices: Some(vertices), 
+                ..raw(ObjectType::Triangle, Some(material))
+            }, 
+            ObjectDescription::Mesh { positions, triangles, normals, uvs, colors, smooth_shading, material } => RawObject {
+                positions: Some(positions), 
+                triangles: Some(triangles), 
+                normals, 
+                uvs, 
+                colors, 
+                smooth_shading, 
+                ..raw(ObjectType::Mesh, Some(material))
+            }, 
+            ObjectDescription::Model { path, material, scale, translate } => RawObject {
+                path: Some(path), 
+                scale: Some(scale), 
+                translate: Some(translate), 
+                ..raw(ObjectType::Model, material)
+            }
+        }
+    }
+}
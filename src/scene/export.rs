@@ -0,0 +1,274 @@
+use crate::{
+    vec3::Vec3, 
+    raytracing::{
+        Material, 
+        material, 
+        hittable
+    }
+};
+use super::{
+    Scene, 
+    SceneError, 
+    SceneDescription, 
+    BackgroundDescription, 
+    MaterialDescription, 
+    ObjectDescription
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet}, 
+    fs, 
+    path::Path, 
+    sync::Arc
+};
+
+
+fn array(v: &Vec3) -> [f64; 3] {
+    [v.x, v.y, v.z]
+}
+
+fn unsupported(field: String, message: &str) -> SceneError {
+    SceneError::Export {field, message: message.to_string()}
+}
+
+fn describe_material(material: &(dyn Material + Send + Sync), field: String) -> Result<MaterialDescription, SceneError> {
+    let material = material.as_any();
+
+    if let Some(lambertian) = material.downcast_ref::<material::Lambertian>() {
+        Ok(MaterialDescription::Lambertian { albedo: array(lambertian.albedo()) })
+    } else if let Some(metal) = material.downcast_ref::<material::Metal>() {
+        Ok(MaterialDescription::Metal { albedo: array(metal.albedo()), fuzziness: metal.fuzziness() })
+    } else if let Some(dielectric) = material.downcast_ref::<material::Dielectric>() {
+        Ok(MaterialDescription::Dielectric { refractive_index: dielectric.refractive_index() })
+    } else if let Some(light) = material.downcast_ref::<material::DiffuseLight>() {
+        Ok(MaterialDescription::DiffuseLight { emit: array(light.emit()) })
+    } else {
+        Err(unsupported(field, "this material cannot be saved to a scene file"))
+    }
+}
+
+/*
+    Gives a name to every material, the same one to all the objects 
+    sharing a material through the same Arc
+*/
+#[derive(Default)]
+struct MaterialNames {
+    names: HashMap<*const Box<dyn Material + Send + Sync>, String>, 
+    descriptions: BTreeMap<String, MaterialDescription>
+}
+
+impl MaterialNames {
+    fn name(&mut self, material: &Arc<Box<dyn Material + Send + Sync>>, field: &str) -> Result<String, SceneError> {
+        let key = Arc::as_ptr(material);
+        if let Some(name) = self.names.get(&key) {
+            return Ok(name.clone());
+        }
+
+        let name = format!("material_{}", self.names.len());
+        let description = describe_material(material.as_ref().as_ref(), format!("{}.material", field))?;
+        self.descriptions.insert(name.clone(), description);
+        self.names.insert(key, name.clone());
+        Ok(name)
+    }
+}
+
+impl Scene {
+    /*
+        Describes the camera and every object of the world so that it can be 
+        saved to a scene file. Materials shared by several objects through 
+        the same Arc are written once and referenced by name
+    */
+    pub fn describe(&self) -> Result<SceneDescription, SceneError> {
+        let mut materials = MaterialNames::default();
+        let mut meshes_written = HashSet::new();
+        let mut objects = vec![];
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let field = format!("objects[{}]", i);
+            let object = object.as_ref().as_any();
+
+            if let Some(sphere) = object.downcast_ref::<hittable::Sphere>() {
+                objects.push(ObjectDescription::Sphere {
+                    center: array(sphere.center()), 
+                    radius: sphere.radius(), 
+                    material: materials.name(sphere.material(), &field)?
+                });
+            } else if let Some(triangle) = object.downcast_ref::<hittable::Triangle>() {
+                let [v0, v1, v2] = triangle.vertices();
+                objects.push(ObjectDescription::Triangle {
+                    vertices: [array(v0), array(v1), array(v2)], 
+                    material: materials.name(triangle.material(), &field)?
+                });
+            } else if let Some(triangle) = object.downcast_ref::<hittable::MeshTriangle>() {
+                // Every triangle of a mesh is a separate object in the world, 
+                // the whole mesh is written when its first triangle is found
+                let mesh = triangle.mesh();
+                if !meshes_written.insert(Arc::as_ptr(mesh)) {
+                    continue;
+                }
+
+                objects.push(ObjectDescription::Mesh {
+                    positions: mesh.positions().iter().map(array).collect(), 
+                    triangles: mesh.indices().to_vec(), 
+                    normals: mesh.normals().map(|normals| normals.iter().map(array).collect()), 
+                    uvs: mesh.uvs().map(|uvs| uvs.iter().map(|(u, v)| [*u, *v]).collect()), 
+                    colors: mesh.colors().map(|colors| colors.iter().map(array).collect()), 
+                    smooth_shading: Some(mesh.smooth_shading()), 
+                    material: materials.name(mesh.material(), &field)?
+                });
+            } else {
+                return Err(unsupported(field, "this kind of object cannot be saved to a scene file"));
+            }
+        }
+
+        // Images are found relative to the scene file, which might be saved 
+        // somewhere else than the one we loaded
+        let background = match &self.background {
+            BackgroundDescription::Image { path, rotation, intensity } if Path::new(path).is_relative() => {
+                let absolute = std::env::current_dir()?.join(path);
+                BackgroundDescription::Image { 
+                    path: absolute.to_string_lossy().into_owned(), 
+                    rotation: *rotation, 
+                    intensity: *intensity 
+                }
+            }, 
+            other => other.clone()
+        };
+
+        Ok(SceneDescription {
+            camera: self.camera.clone(), 
+            background, 
+            render: self.render.clone(), 
+            materials: materials.descriptions, 
+            objects
+        })
+    }
+
+    /*
+        Saves the scene to a .json or .toml file that loads back to the 
+        exact same world
+    */
+    pub fn save(&self, path: &Path) -> Result<(), SceneError> {
+        let format = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+        let text = self.describe()?.to_text(&format)?;
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    /*
+        A hash of the scene description, to tell whether a render was 
+        started from this same scene. The seed is left out, it is not part 
+        of the scene itself
+    */
+    pub fn fingerprint(&self) -> Result<u64, SceneError> {
+        let mut description = self.describe()?;
+        description.render.seed = None;
+        Ok(fnv1a(description.to_text("json")?.as_bytes()))
+    }
+}
+
+/*
+    The 64 bit FNV-1a hash
+*/
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+impl SceneDescription {
+    pub fn to_text(&self, format: &str) -> Result<String, SceneError> {
+        let export_error = |message: String| SceneError::Export {field: String::new(), message};
+
+        match format {
+            "json" => serde_json::to_string_pretty(self).map_err(|e| export_error(e.to_string())), 
+            "toml" => toml::to_string(self).map_err(|e| export_error(e.to_string())), 
+            other => Err(SceneError::UnsupportedFormat(other.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{CameraDescription, RenderDescription};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use std::cell::RefCell;
+
+    fn random_scene() -> Scene {
+        let rng = RefCell::new(StdRng::seed_from_u64(2));
+        let rand_val = |min: f64, max: f64| min + (max - min) * rng.borrow_mut().gen::<f64>();
+        let rand_vec = || Vec3::new(rand_val(-1., 1.), rand_val(-1., 1.), rand_val(-1., 1.));
+
+        let shared: Arc<Box<dyn Material + Send + Sync>> = Arc::new(Box::new(material::Dielectric::new(1.5)));
+        let mut objects: Vec<Box<dyn hittable::Hittable + Send + Sync>> = vec![];
+        for i in 0..100 {
+            let material: Arc<Box<dyn Material + Send + Sync>> = match i % 5 {
+                0 => Arc::new(Box::new(material::Lambertian::new(rand_vec()))), 
+                1 => Arc::new(Box::new(material::Metal::new(rand_vec(), rand_val(0., 1.)))), 
+                2 => Arc::new(Box::new(material::Dielectric::new(rand_val(1., 2.)))), 
+                3 => Arc::new(Box::new(material::DiffuseLight::new(Vec3::new(rand_val(0., 10.), rand_val(0., 10.), rand_val(0., 10.))))), 
+                _ => shared.clone()
+            };
+            objects.push(Box::new(hittable::Sphere::new(rand_vec() * 10., rand_val(0.1, 1.), material)));
+        }
+        objects.push(Box::new(hittable::Triangle::new(rand_vec(), rand_vec(), rand_vec(), shared.clone())));
+        let mesh = hittable::TriangleMesh::new(
+            vec![rand_vec(), rand_vec(), rand_vec(), rand_vec()], 
+            vec![[0, 1, 2], [0, 2, 3]], 
+            shared.clone()
+        ).with_normals(vec![rand_vec(), rand_vec(), rand_vec(), rand_vec()])
+         .with_uvs(vec![(0., 0.), (1., 0.), (1., 1.), (rand_val(0., 1.), 1.)])
+         .with_colors(vec![rand_vec(), rand_vec(), rand_vec(), rand_vec()]);
+        objects.extend(mesh.into_hittables());
+        let (max_depth, seed) = rng.borrow_mut().gen::<(u32, u32)>();
+
+        Scene {
+            camera: CameraDescription {
+                look_from: array(&rand_vec()), 
+                look_at: [0.1, 0.2, 0.3], 
+                vup: [0., 1., 0.], 
+                vertical_fov: rand_val(10., 90.), 
+                aperture: rand_val(0., 1.), 
+                focus_distance: Some(rand_val(1., 10.))
+            }, 
+            render: RenderDescription {max_depth: Some(max_depth), roulette_depth: None, seed: Some(seed as u64)}, 
+            background: BackgroundDescription::Gradient {
+                bottom: [rand_val(0., 1.), rand_val(0., 1.), rand_val(0., 1.)], 
+                top: [rand_val(0., 1.), 0.7, 1.]
+            }, 
+            objects
+        }
+    }
+
+    #[test]
+    fn shared_materials_are_written_once() {
+        let description = random_scene().describe().unwrap();
+        // 80 materials of their own plus the shared one
+        assert_eq!(description.materials.len(), 81);
+        // The two triangles of the mesh are written as a single object
+        assert_eq!(description.objects.len(), 102);
+    }
+
+    #[test]
+    fn round_trips_exactly() {
+        for format in ["json", "toml"].iter() {
+            let description = random_scene().describe().unwrap();
+            let text = description.to_text(format).unwrap();
+
+            let reloaded = SceneDescription::parse(&text, format).unwrap().build(Path::new("")).unwrap();
+            assert_eq!(reloaded.describe().unwrap(), description);
+        }
+    }
+
+    #[test]
+    fn fingerprint_ignores_the_seed() {
+        let mut scene = random_scene();
+        let fingerprint = scene.fingerprint().unwrap();
+        let reloaded = SceneDescription::parse(&scene.describe().unwrap().to_text("json").unwrap(), "json")
+            .unwrap().build(Path::new("")).unwrap();
+        assert_eq!(reloaded.fingerprint().unwrap(), fingerprint);
+
+        scene.render.seed = Some(12);
+        assert_eq!(scene.fingerprint().unwrap(), fingerprint);
+        scene.render.roulette_depth = Some(12);
+        assert_ne!(scene.fingerprint().unwrap(), fingerprint);
+    }
+}
@@ -0,0 +1,467 @@
+mod description;
+mod export;
+
+use crate::{
+    vec3::Vec3, 
+    raytracing::{
+        Camera, 
+        Material, 
+        RenderSettings, 
+        material, 
+        hittable::{self, Hittable, Aabb}, 
+        background::{self, Background}
+    }, 
+    model_formats, 
+    image_formats
+};
+use std::{
+    collections::HashMap, 
+    fmt, 
+    fs, 
+    path::Path, 
+    str::FromStr, 
+    sync::Arc
+};
+
+use description::default_vup;
+pub use description::{
+    SceneDescription, 
+    CameraDescription, 
+    BackgroundDescription, 
+    RenderDescription, 
+    MaterialDescription, 
+    ObjectDescription
+};
+
+
+/*
+    A world ready to be rendered: the objects still need to be put inside 
+    a BVH, the camera is only built once the image aspect ratio is known and 
+    the background once we know it is not replaced from the command line
+*/
+pub struct Scene {
+    pub camera: CameraDescription, 
+    pub background: BackgroundDescription, 
+    pub render: RenderDescription, 
+    pub objects: Vec<Box<dyn Hittable + Send + Sync>>
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error), 
+    UnsupportedFormat(String), 
+    // The path of the field that failed, in the form objects[2].radius
+    Parse { field: String, message: String }, 
+    Invalid { field: String, message: String }, 
+    Export { field: String, message: String }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{}", e), 
+            SceneError::UnsupportedFormat(extension) => write!(
+                f, "unsupported scene format '{}', expected .json or .toml", extension
+            ), 
+            SceneError::Parse { field, message } if field.is_empty() || field == "." => write!(f, "{}", message), 
+            SceneError::Parse { field, message } => write!(f, "{}: {}", field, message), 
+            SceneError::Invalid { field, message } => write!(f, "{}: {}", field, message), 
+            SceneError::Export { field, message } if field.is_empty() => write!(f, "{}", message), 
+            SceneError::Export { field, message } => write!(f, "{}: {}", field, message)
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> SceneError { SceneError::Io(e) }
+}
+
+fn invalid(field: String, message: &str) -> SceneError {
+    SceneError::Invalid {field, message: message.to_string()}
+}
+
+fn vec3(v: &[f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+impl CameraDescription {
+    /*
+        A camera looking at the box from the front and slightly above, 
+        far enough to see all of it
+    */
+    pub fn framing(bbox: &Aabb, vertical_fov: f64) -> CameraDescription {
+        let center = bbox.centroid();
+        let radius = (0.5 * (bbox.maximum - bbox.minimum).length()).max(1e-3);
+        let distance = 1.1 * radius / (0.5 * vertical_fov.to_radians()).sin();
+        let look_from = center + distance * Vec3::new(0.3, 0.4, 1.).unit_vector();
+
+        CameraDescription {
+            look_from: [look_from.x, look_from.y, look_from.z], 
+            look_at: [center.x, center.y, center.z], 
+            vup: default_vup(), 
+            vertical_fov, 
+            aperture: 0., 
+            focus_distance: None
+        }
+    }
+
+    pub fn to_camera(&self, aspect_ratio: f64) -> Camera {
+        let look_from = vec3(&self.look_from);
+        let look_at = vec3(&self.look_at);
+        let focus_distance = self.focus_distance.unwrap_or_else(|| (look_from - look_at).length());
+
+        Camera::new(
+            &look_from, &look_at, &vec3(&self.vup), 
+            self.vertical_fov, 
+            aspect_ratio, 
+            self.aperture, 
+            focus_distance
+        )
+    }
+
+    fn validate(&self) -> Result<(), SceneError> {
+        if self.look_from == self.look_at {
+            return Err(invalid("camera.look_at".to_string(), "must be different from look_from"));
+        }
+        if self.vup == [0., 0., 0.] {
+            return Err(invalid("camera.vup".to_string(), "must not be a zero vector"));
+        }
+        if !(self.vertical_fov > 0. && self.vertical_fov < 180.) {
+            return Err(invalid("camera.vertical_fov".to_string(), "must be between 0 and 180 degrees"));
+        }
+        if self.aperture < 0. {
+            return Err(invalid("camera.aperture".to_string(), "must not be negative"));
+        }
+        if let Some(d) = self.focus_distance {
+            if d <= 0. {
+                return Err(invalid("camera.focus_distance".to_string(), "must be positive"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RenderDescription {
+    pub fn to_settings(&self) -> RenderSettings {
+        let defaults = RenderSettings::default();
+        RenderSettings {
+            max_depth: self.max_depth.unwrap_or(defaults.max_depth), 
+            roulette_depth: self.roulette_depth.unwrap_or(defaults.roulette_depth), 
+            seed: self.seed.unwrap_or(defaults.seed), 
+            ..defaults
+        }
+    }
+}
+
+impl BackgroundDescription {
+    pub fn build(&self) -> Result<Box<dyn Background + Send + Sync>, SceneError> {
+        Ok(match self {
+            BackgroundDescription::Black => Box::new(background::SolidColor::new(Vec3::ZERO)), 
+            BackgroundDescription::Color { color } => Box::new(background::SolidColor::new(vec3(color))), 
+            BackgroundDescription::Gradient { bottom, top } => Box::new(background::Gradient::new(vec3(bottom), vec3(top))), 
+            BackgroundDescription::Image { path, rotation, intensity } => {
+                let image = image_formats::read_linear(Path::new(path))
+                    .map_err(|e| invalid("background.path".to_string(), &format!("{}: {}", path, e)))?;
+                Box::new(background::EnvironmentMap::new(image.width(), image.height(), image.into_pixels())
+                    .with_rotation(*rotation)
+                    .with_intensity(*intensity))
+            }
+        })
+    }
+
+    /*
+        Makes the path to the image relative to 'base_folder', and checks 
+        that the colors make sense
+    */
+    fn resolve(&self, base_folder: &Path) -> Result<BackgroundDescription, SceneError> {
+        let check = |color: &[f64; 3], field: &str| match color.iter().any(|c| *c < 0.) {
+            true => Err(invalid(format!("background.{}", field), "must not be negative")), 
+            false => Ok(())
+        };
+
+        match self {
+            BackgroundDescription::Black => {}, 
+            BackgroundDescription::Color { color } => check(color, "color")?, 
+            BackgroundDescription::Gradient { bottom, top } => {
+                check(bottom, "bottom")?;
+                check(top, "top")?;
+            }, 
+            BackgroundDescription::Image { path, rotation, intensity } => {
+                if *intensity < 0. {
+                    return Err(invalid("background.intensity".to_string(), "must not be negative"));
+                }
+                return Ok(BackgroundDescription::Image {
+                    path: base_folder.join(path).to_string_lossy().into_owned(), 
+                    rotation: *rotation, 
+                    intensity: *intensity
+                });
+            }
+        }
+        Ok(self.clone())
+    }
+}
+
+/*
+    The value of the --background option: black, gradient, a color written 
+    as r,g,b or the path to an image
+*/
+impl FromStr for BackgroundDescription {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BackgroundDescription, String> {
+        match s {
+            "black" => return Ok(BackgroundDescription::Black), 
+            "gradient" => return Ok(BackgroundDescription::default()), 
+            _ => {}
+        }
+
+        let components = s.split(',').map(|c| c.trim().parse::<f64>()).collect::<Vec<_>>();
+        match components.as_slice() {
+            [Ok(r), Ok(g), Ok(b)] => Ok(BackgroundDescription::Color { color: [*r, *g, *b] }), 
+            _ if s.contains(',') => Err(format!("'{}' is not a color, expected three numbers like 0.1,0.2,0.3", s)), 
+            _ => Ok(BackgroundDescription::Image { path: s.to_string(), rotation: 0., intensity: 1. })
+        }
+    }
+}
+
+impl MaterialDescription {
+    fn build(&self, name: &str) -> Result<Arc<Box<dyn Material + Send + Sync>>, SceneError> {
+        let field = |f: &str| format!("materials.{}.{}", name, f);
+
+        let material: Box<dyn Material + Send + Sync> = match self {
+            MaterialDescription::Lambertian { albedo } => Box::new(material::Lambertian::new(vec3(albedo))), 
+            MaterialDescription::Metal { albedo, fuzziness } => {
+                if !(0. ..=1.).contains(fuzziness) {
+                    return Err(invalid(field("fuzziness"), "must be between 0 and 1"));
+                }
+                Box::new(material::Metal::new(vec3(albedo), *fuzziness))
+            }, 
+            MaterialDescription::Dielectric { refractive_index } => {
+                if *refractive_index <= 0. {
+                    return Err(invalid(field("refractive_index"), "must be positive"));
+                }
+                Box::new(material::Dielectric::new(*refractive_index))
+            }, 
+            MaterialDescription::DiffuseLight { emit } => {
+                if emit.iter().any(|c| *c < 0.) {
+                    return Err(invalid(field("emit"), "must not be negative"));
+                }
+                Box::new(material::DiffuseLight::new(vec3(emit)))
+            }
+        };
+
+        Ok(Arc::new(material))
+    }
+}
+
+impl SceneDescription {
+    pub fn parse(text: &str, format: &str) -> Result<SceneDescription, SceneError> {
+        let parse_error = |field: String, message: String| SceneError::Parse {field, message};
+
+        match format {
+            "json" => {
+                let deserializer = &mut serde_json::Deserializer::from_str(text);
+                serde_path_to_error::deserialize(deserializer)
+                    .map_err(|e| parse_error(e.path().to_string(), e.inner().to_string()))
+            }, 
+            "toml" => {
+                let deserializer = &mut toml::Deserializer::new(text);
+                serde_path_to_error::deserialize(deserializer)
+                    .map_err(|e| parse_error(e.path().to_string(), e.inner().to_string()))
+            }, 
+            other => Err(SceneError::UnsupportedFormat(other.to_string()))
+        }
+    }
+
+    /*
+        Paths to model and image files are relative to base_folder
+    */
+    pub fn build(&self, base_folder: &Path) -> Result<Scene, SceneError> {
+        self.camera.validate()?;
+        let background = self.background.resolve(base_folder)?;
+
+        let mut materials = HashMap::new();
+        for (name, description) in &self.materials {
+            materials.insert(name.as_str(), description.build(name)?);
+        }
+
+        let mut objects: Vec<Box<dyn Hittable + Send + Sync>> = vec![];
+        for (i, object) in self.objects.iter().enumerate() {
+            let field = |f: &str| format!("objects[{}].{}", i, f);
+            let material = |name: &String| materials.get(name.as_str()).cloned().ok_or_else(|| invalid(
+                field("material"), 
+                &format!("unknown material '{}'", name)
+            ));
+
+            match object {
+                ObjectDescription::Sphere { center, radius, material: name } => {
+                    let material = material(name)?;
+                    if *radius == 0. || !radius.is_finite() {
+                        return Err(invalid(field("radius"), "must be a non-zero number"));
+                    }
+                    objects.push(Box::new(hittable::Sphere::new(vec3(center), *radius, material)));
+                }, 
+                ObjectDescription::Triangle { vertices, material: name } => {
+                    let material = material(name)?;
+                    let [v0, v1, v2] = vertices;
+                    objects.push(Box::new(hittable::Triangle::new(vec3(v0), vec3(v1), vec3(v2), material)));
+                }, 
+                ObjectDescription::Mesh { positions, triangles, normals, uvs, colors, smooth_shading, material: name } => {
+                    let material = material(name)?;
+                    for (j, triangle) in triangles.iter().enumerate() {
+                        if let Some(index) = triangle.iter().find(|index| **index >= positions.len()) {
+                            return Err(invalid(
+                                format!("objects[{}].triangles[{}]", i, j), 
+                                &format!("vertex {} does not exist, the mesh has {} positions", index, positions.len())
+                            ));
+                        }
+                    }
+
+                    let mut mesh = hittable::TriangleMesh::new(
+                        positions.iter().map(vec3).collect(), 
+                        triangles.clone(), 
+                        material
+                    );
+                    if let Some(normals) = normals {
+                        if normals.len() != positions.len() {
+                            return Err(invalid(field("normals"), "must have as many normals as positions"));
+                        }
+                        mesh = mesh.with_normals(normals.iter().map(vec3).collect());
+                    }
+                    if let Some(uvs) = uvs {
+                        if uvs.len() != positions.len() {
+                            return Err(invalid(field("uvs"), "must have as many uvs as positions"));
+                        }
+                        mesh = mesh.with_uvs(uvs.iter().map(|[u, v]| (*u, *v)).collect());
+                    }
+                    if let Some(colors) = colors {
+                        if colors.len() != positions.len() {
+                            return Err(invalid(field("colors"), "must have as many colors as positions"));
+                        }
+                        mesh = mesh.with_colors(colors.iter().map(vec3).collect());
+                    }
+                    if let Some(smooth_shading) = smooth_shading {
+                        mesh = mesh.with_smooth_shading(*smooth_shading);
+                    }
+                    objects.extend(mesh.into_hittables());
+                }, 
+                ObjectDescription::Model { path, material: name, scale, translate } => {
+                    let material = name.as_ref().map(material).transpose()?;
+                    if *scale <= 0. {
+                        return Err(invalid(field("scale"), "must be positive"));
+                    }
+
+                    let meshes = model_formats::load(&base_folder.join(path), material)
+                        .map_err(|e| invalid(field("path"), &format!("{}: {}", path, e)))?;
+                    for mesh in meshes {
+                        objects.extend(mesh.transformed(*scale, vec3(translate)).into_hittables());
+                    }
+                }
+            }
+        }
+
+        Ok(Scene {camera: self.camera.clone(), background, render: self.render.clone(), objects})
+    }
+}
+
+/*
+    Loads a scene from a .json or .toml file, see the README for a 
+    description of the format
+*/
+pub fn load(path: &Path) -> Result<Scene, SceneError> {
+    let format = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    let text = fs::read_to_string(path)?;
+
+    let folder = path.parent().unwrap_or_else(|| Path::new(""));
+    SceneDescription::parse(&text, &format)?.build(folder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = r#"{
+        "camera": {"look_from": [0, 0, 5], "look_at": [0, 0, 0], "vertical_fov": 40},
+        "materials": {
+            "red": {"type": "lambertian", "albedo": [0.8, 0.1, 0.1]},
+            "glass": {"type": "dielectric", "refractive_index": 1.5}
+        },
+        "objects": [
+            {"type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "red"},
+            {"type": "sphere", "center": [2, 0, 0], "radius": 0.5, "material": "glass"}
+        ]
+    }"#;
+
+    fn error_message(text: &str, format: &str) -> String {
+        match SceneDescription::parse(text, format).and_then(|d| d.build(Path::new(""))) {
+            Ok(_) => panic!("expected the scene to fail loading"), 
+            Err(e) => e.to_string()
+        }
+    }
+
+    #[test]
+    fn loads_json_scene() {
+        let scene = SceneDescription::parse(SCENE, "json").unwrap().build(Path::new("")).unwrap();
+        assert_eq!(scene.objects.len(), 2);
+        assert_eq!(scene.camera.vup, [0., 1., 0.]);
+    }
+
+    #[test]
+    fn loads_toml_scene() {
+        let text = r#"
+            [camera]
+            look_from = [0.0, 0.0, 5.0]
+            look_at = [0.0, 0.0, 0.0]
+            vertical_fov = 40.0
+
+            [materials.steel]
+            type = "metal"
+            albedo = [0.7, 0.7, 0.7]
+            fuzziness = 0.1
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, 0.0]
+            radius = 1.0
+            material = "steel"
+        "#;
+        let scene = SceneDescription::parse(text, "toml").unwrap().build(Path::new("")).unwrap();
+        assert_eq!(scene.objects.len(), 1);
+    }
+
+    #[test]
+    fn reports_the_bad_field() {
+        let bad_type = SCENE.replace(r#""radius": 0.5"#, r#""radius": "big""#);
+        assert!(error_message(&bad_type, "json").contains("objects[1]"));
+
+        let unknown_material = SCENE.replace(r#""material": "glass""#, r#""material": "gold""#);
+        assert_eq!(error_message(&unknown_material, "json"), "objects[1].material: unknown material 'gold'");
+
+        let bad_fov = SCENE.replace(r#""vertical_fov": 40"#, r#""vertical_fov": "wide""#);
+        assert!(error_message(&bad_fov, "json").starts_with("camera.vertical_fov"));
+
+        let typo = SCENE.replace("look_at", "look_to");
+        assert!(error_message(&typo, "json").contains("look_to"));
+
+        let bad_background = SCENE.replace(r#""materials""#, r#""background": {"type": "color"}, "materials""#);
+        assert!(error_message(&bad_background, "json").starts_with("background: missing field `color`"));
+    }
+
+    #[test]
+    fn backgrounds() {
+        let scene = SceneDescription::parse(SCENE, "json").unwrap().build(Path::new("")).unwrap();
+        assert_eq!(scene.background, BackgroundDescription::default());
+
+        let with_image = SCENE.replace(r#""materials""#, r#""background": {"type": "image", "path": "sky.png"}, "materials""#);
+        let scene = SceneDescription::parse(&with_image, "json").unwrap().build(Path::new("scenes")).unwrap();
+        let expected = Path::new("scenes").join("sky.png").to_string_lossy().into_owned();
+        assert_eq!(scene.background, BackgroundDescription::Image { path: expected, rotation: 0., intensity: 1. });
+
+        assert_eq!("black".parse(), Ok(BackgroundDescription::Black));
+        assert_eq!("0.1, 0.2,0.3".parse(), Ok(BackgroundDescription::Color { color: [0.1, 0.2, 0.3] }));
+        assert_eq!("sky.hdr".parse(), Ok(BackgroundDescription::Image { path: "sky.hdr".to_string(), rotation: 0., intensity: 1. }));
+        assert!("0.1,blue,0.3".parse::<BackgroundDescription>().is_err());
+    }
+}
@@ -37,6 +37,7 @@ impl Vec3 {
         }
     }
 
+    #[allow(clippy::redundant_field_names)]
     pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
         Vec3 { x: x, y: y, z: z }
     }
@@ -71,6 +72,7 @@ impl Vec3 {
 
     // Returns a new copy of self with the x-value replaced
     // with the specified value.
+    #[allow(clippy::needless_return, clippy::redundant_field_names)]
     pub fn with_x(self, x: f64) -> Vec3 {
         return Vec3 {
             x: x,
@@ -81,6 +83,7 @@ impl Vec3 {
 
     // Returns a new copy of self with the y-value replaced
     // with the specified value.
+    #[allow(clippy::needless_return, clippy::redundant_field_names)]
     pub fn with_y(self, y: f64) -> Vec3 {
         return Vec3 {
             x: self.x,
@@ -91,6 +94,7 @@ impl Vec3 {
 
     // Returns a new copy of self with the z-value replaced
     // with the specified value.
+    #[allow(clippy::needless_return, clippy::redundant_field_names)]
     pub fn with_z(self, z: f64) -> Vec3 {
         return Vec3 {
             x: self.x,
@@ -103,10 +107,12 @@ impl Vec3 {
         self / self.length()
     }
 
+    #[allow(clippy::needless_return)]
     pub fn length(&self) -> f64 {
         return self.length_squared().sqrt();
     }
 
+    #[allow(clippy::needless_return)]
     pub fn min(&self, other: &Vec3) -> Vec3 {
         return Vec3 {
             x: self.x.min(other.x),
@@ -115,6 +121,7 @@ impl Vec3 {
         };
     }
 
+    #[allow(clippy::needless_return)]
     pub fn max(&self, other: &Vec3) -> Vec3 {
         return Vec3 {
             x: self.x.max(other.x),
@@ -123,14 +130,17 @@ impl Vec3 {
         };
     }
 
+    #[allow(clippy::needless_return)]
     pub fn length_squared(&self) -> f64 {
         return self.x * self.x + self.y * self.y + self.z * self.z;
     }
 
+    #[allow(clippy::needless_return)]
     pub fn dot(a: &Vec3, b: &Vec3) -> f64 {
         return a.x * b.x + a.y * b.y + a.z * b.z;
     }
 
+    #[allow(clippy::needless_return)]
     pub fn cross(a: &Vec3, b: &Vec3) -> Vec3 {
         return Vec3 {
             x: a.y * b.z - a.z * b.y,
@@ -139,23 +149,9 @@ impl Vec3 {
         };
     }
 
-    fn random_range(min: f64, max: f64) -> Vec3 {
-        let rand_range = || (min + rand::random::<f64>() * (max - min));
-        Vec3::new(rand_range(), rand_range(), rand_range())
-    }
-
-    pub fn random_in_unit_sphere() -> Vec3 {
-        // TODO: Actual smart random gen
-        loop {
-            let v = Vec3::random_range(-1., 1.);
-            if v.length_squared() < 1. {
-                return v;
-            }
-        }
-    }
-
-    pub fn random_unit_vector() -> Vec3 {
-        Vec3::random_in_unit_sphere().unit_vector()
+    // How bright a linear RGB color looks
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
     }
 
     pub fn near_zero(&self) -> bool {
@@ -163,6 +159,7 @@ impl Vec3 {
         self.x.abs() < MARGIN && self.y.abs() < MARGIN && self.z.abs() < MARGIN
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_color(&self) -> (u8, u8, u8) {
         (
             (255.999 * self.x) as u8,
@@ -363,13 +360,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn random_in_unit_sphere() {
-        for _ in 0..100 {
-            assert!(Vec3::random_in_unit_sphere().length_squared() < 1.);
-        }
-    }
-
-    #[test]
+    #[allow(clippy::op_ref)]
     fn add() {
         let a = Vec3::new(0.0, 1.0, 2.0);
         let b = Vec3::new(3.0, 4.0, 5.0);
@@ -394,6 +385,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::op_ref)]
     fn subtract() {
         let a = Vec3::new(0.0, 1.0, 2.0);
         let b = Vec3::new(3.0, 4.0, 5.0);
@@ -418,6 +410,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::op_ref)]
     fn multiply() {
         let a = Vec3::new(0.0, 1.0, 2.0);
         let b = Vec3::new(3.0, 4.0, 5.0);
@@ -442,6 +435,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::op_ref)]
     fn divide() {
         let a = Vec3::new(1.0, 1.0, 2.0);
         let b = Vec3::new(3.0, 4.0, 5.0);
@@ -480,6 +474,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn length() {
         let a = Vec3::new(3.0, 2.0, 1.0);
         assert_eq!(